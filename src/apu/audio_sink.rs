@@ -0,0 +1,49 @@
+use crate::apu::SAMPLE_RATE;
+use sdl2::audio::AudioCallback;
+use std::collections::VecDeque;
+
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize / 4;
+
+// Repeats the last sample when it runs dry to avoid clicks.
+pub struct AudioSink {
+    buffer: VecDeque<f32>,
+    last_sample: f32,
+}
+
+impl Default for AudioSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioCallback for AudioSink {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            if let Some(sample) = self.buffer.pop_front() {
+                self.last_sample = sample;
+            }
+            *x = self.last_sample;
+        }
+    }
+}
+
+impl AudioSink {
+    pub fn new() -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            last_sample: 0.0,
+        }
+    }
+
+    pub fn buffered_samples(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        self.buffer.extend(samples);
+        let overflow = self.buffer.len().saturating_sub(MAX_BUFFERED_SAMPLES);
+        self.buffer.drain(..overflow);
+    }
+}
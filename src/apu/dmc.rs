@@ -0,0 +1,141 @@
+use crate::nes::region::Region;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dmc {
+    is_irq_enable: bool,
+    is_loop: bool,
+    rate_index: u8,
+    timer: u16,
+    output_level: u8,
+    sample_addr: u16,
+    sample_length: u16,
+    current_addr: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    is_silence: bool,
+    pub irq: bool,
+    region: Region,
+}
+
+impl Dmc {
+    pub fn new(region: Region) -> Self {
+        Self {
+            is_irq_enable: false,
+            is_loop: false,
+            rate_index: 0,
+            timer: region.dmc_rates()[0],
+            output_level: 0,
+            sample_addr: 0xC000,
+            sample_length: 1,
+            current_addr: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            is_silence: true,
+            irq: false,
+            region,
+        }
+    }
+
+    pub fn set(&mut self, addr: u8, data: u8) {
+        match addr {
+            0 => {
+                self.is_irq_enable = (data & 0b10000000) != 0;
+                self.is_loop = (data & 0b01000000) != 0;
+                self.rate_index = data & 0b00001111;
+                if !self.is_irq_enable {
+                    self.irq = false;
+                }
+            }
+            1 => self.output_level = data & 0b01111111,
+            2 => self.sample_addr = 0xC000 + data as u16 * 64,
+            3 => self.sample_length = data as u16 * 16 + 1,
+            _ => unreachable!(),
+        }
+    }
+
+    // Setting bit 4 of $4015 restarts the sample only when the last one has finished.
+    pub fn set_enable(&mut self, is_enable: bool) {
+        self.irq = false;
+        if !is_enable {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn dma_addr(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_addr)
+        } else {
+            None
+        }
+    }
+
+    pub fn load_sample(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_addr = if self.current_addr == 0xFFFF {
+            0x8000
+        } else {
+            self.current_addr + 1
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.is_loop {
+                self.restart();
+            } else if self.is_irq_enable {
+                self.irq = true;
+            }
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.region.dmc_rates()[self.rate_index as usize];
+        self.clock_output();
+    }
+
+    fn clock_output(&mut self) {
+        if !self.is_silence {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(data) => {
+                    self.is_silence = false;
+                    self.shift_register = data;
+                }
+                None => self.is_silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
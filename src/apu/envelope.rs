@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    is_start: bool,
+    divider: u8,
+    decay_level: u8,
+    is_loop: bool,
+    is_constant_volume: bool,
+    volume: u8,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            is_start: false,
+            divider: 0,
+            decay_level: 0,
+            is_loop: false,
+            is_constant_volume: false,
+            volume: 0,
+        }
+    }
+
+    pub fn addr(&self) -> u8 {
+        let mut data = 0;
+        data += (self.is_loop as u8) << 5;
+        data += (self.is_constant_volume as u8) << 4;
+        data += self.volume;
+
+        data
+    }
+
+    pub fn set(&mut self, data: u8) {
+        self.is_loop = (data & 0b00100000) != 0;
+        self.is_constant_volume = (data & 0b00010000) != 0;
+        self.volume = data & 0b00001111;
+    }
+
+    pub fn restart(&mut self) {
+        self.is_start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.is_start {
+            self.is_start = false;
+            self.decay_level = 15;
+            self.divider = self.volume;
+        } else if self.divider > 0 {
+            self.divider -= 1;
+        } else {
+            self.divider = self.volume;
+            if self.decay_level > 0 {
+                self.decay_level -= 1;
+            } else if self.is_loop {
+                self.decay_level = 15;
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.is_constant_volume {
+            self.volume
+        } else {
+            self.decay_level
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::apu::APU;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LengthCounter {
+    is_enable: bool,
+    is_halt: bool,
+    counter: u16,
+}
+
+impl Default for LengthCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LengthCounter {
+    fn new() -> Self {
+        Self {
+            is_enable: false,
+            is_halt: false,
+            counter: 0,
+        }
+    }
+
+    pub fn set_halt(&mut self, is_halt: bool) {
+        self.is_halt = is_halt;
+    }
+
+    pub fn load(&mut self, index: u8) {
+        if self.is_enable {
+            self.counter = APU::LENGTH_COUNTER[index as usize];
+        }
+    }
+
+    pub fn set_enable(&mut self, is_enable: bool) {
+        self.is_enable = is_enable;
+        if !is_enable {
+            self.counter = 0;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.counter > 0 && !self.is_halt {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HighPass {
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32,
+}
+
+impl HighPass {
+    fn new(cutoff_hz: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff_hz);
+        let dt = 1.0 / sample_rate;
+        Self {
+            alpha: rc / (rc + dt),
+            prev_input: 0.0,
+            prev_output: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        self.prev_output = self.alpha * (self.prev_output + input - self.prev_input);
+        self.prev_input = input;
+        self.prev_output
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LowPass {
+    alpha: f32,
+    prev_output: f32,
+}
+
+impl LowPass {
+    fn new(cutoff_hz: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff_hz);
+        let dt = 1.0 / sample_rate;
+        Self {
+            alpha: dt / (rc + dt),
+            prev_output: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        self.prev_output += self.alpha * (input - self.prev_output);
+        self.prev_output
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputFilter {
+    high_pass_90: HighPass,
+    high_pass_440: HighPass,
+    low_pass_14k: LowPass,
+}
+
+impl OutputFilter {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            high_pass_90: HighPass::new(90.0, sample_rate),
+            high_pass_440: HighPass::new(440.0, sample_rate),
+            low_pass_14k: LowPass::new(14000.0, sample_rate),
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let filtered = self.high_pass_90.process(sample);
+        let filtered = self.high_pass_440.process(filtered);
+        self.low_pass_14k.process(filtered)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 5] = [
+        Channel::Pulse1,
+        Channel::Pulse2,
+        Channel::Triangle,
+        Channel::Noise,
+        Channel::Dmc,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "pulse1",
+            Channel::Pulse2 => "pulse2",
+            Channel::Triangle => "triangle",
+            Channel::Noise => "noise",
+            Channel::Dmc => "dmc",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+// Not part of save states, loading one keeps the current settings.
+#[derive(Debug, Clone)]
+pub struct ChannelSettings {
+    muted: [bool; 5],
+    soloed: [bool; 5],
+    gains: [f32; 5],
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self {
+            muted: [false; 5],
+            soloed: [false; 5],
+            gains: [1.0; 5],
+        }
+    }
+}
+
+impl ChannelSettings {
+    pub fn set_mute(&mut self, channel: Channel, is_muted: bool) {
+        self.muted[channel.index()] = is_muted;
+    }
+
+    pub fn is_muted(&self, channel: Channel) -> bool {
+        self.muted[channel.index()]
+    }
+
+    pub fn set_solo(&mut self, channel: Channel, is_soloed: bool) {
+        self.soloed[channel.index()] = is_soloed;
+    }
+
+    pub fn is_soloed(&self, channel: Channel) -> bool {
+        self.soloed[channel.index()]
+    }
+
+    pub fn set_gain(&mut self, channel: Channel, gain: f32) {
+        self.gains[channel.index()] = gain.max(0.0);
+    }
+
+    pub fn gain(&self, channel: Channel) -> f32 {
+        self.gains[channel.index()]
+    }
+
+    pub fn is_audible(&self, channel: Channel) -> bool {
+        let is_any_soloed = self.soloed.iter().any(|soloed| *soloed);
+        !self.is_muted(channel) && (!is_any_soloed || self.is_soloed(channel))
+    }
+
+    fn scale(&self, channel: Channel, level: u8) -> f32 {
+        if self.is_audible(channel) {
+            level as f32 * self.gain(channel)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mixer {
+    pulse_table: Vec<f32>,
+    tnd_table: Vec<f32>,
+    output_filter: OutputFilter,
+    #[serde(skip)]
+    pub channel_settings: ChannelSettings,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: f32) -> Self {
+        let pulse_table = (0..31)
+            .map(|n| match n {
+                0 => 0.0,
+                n => 95.52 / (8128.0 / n as f32 + 100.0),
+            })
+            .collect();
+        let tnd_table = (0..203)
+            .map(|n| match n {
+                0 => 0.0,
+                n => 163.67 / (24329.0 / n as f32 + 100.0),
+            })
+            .collect();
+        Self {
+            pulse_table,
+            tnd_table,
+            output_filter: OutputFilter::new(sample_rate),
+            channel_settings: ChannelSettings::default(),
+        }
+    }
+
+    // Gains make the table index fractional, so neighbouring entries are interpolated.
+    fn lookup(table: &[f32], index: f32) -> f32 {
+        let index = index.min((table.len() - 1) as f32);
+        let low = index as usize;
+        let high = (low + 1).min(table.len() - 1);
+        let frac = index - low as f32;
+        table[low] + (table[high] - table[low]) * frac
+    }
+
+    pub fn mix(&self, pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        let settings = &self.channel_settings;
+        let pulse =
+            settings.scale(Channel::Pulse1, pulse1) + settings.scale(Channel::Pulse2, pulse2);
+        let tnd = 3.0 * settings.scale(Channel::Triangle, triangle)
+            + 2.0 * settings.scale(Channel::Noise, noise)
+            + settings.scale(Channel::Dmc, dmc);
+        Self::lookup(&self.pulse_table, pulse) + Self::lookup(&self.tnd_table, tnd)
+    }
+
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        self.output_filter.process(sample)
+    }
+
+    pub fn mix_channel(&self, channel: Channel, level: u8) -> f32 {
+        match channel {
+            Channel::Pulse1 | Channel::Pulse2 => self.pulse_table[level as usize],
+            Channel::Triangle => self.tnd_table[3 * level as usize],
+            Channel::Noise => self.tnd_table[2 * level as usize],
+            Channel::Dmc => self.tnd_table[level as usize],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::apu::mixer::*;
+
+    #[test]
+    fn solo_silences_other_channels() {
+        let mut settings = ChannelSettings::default();
+        settings.set_solo(Channel::Triangle, true);
+        assert!(settings.is_audible(Channel::Triangle));
+        assert!(!settings.is_audible(Channel::Pulse1));
+        assert!(!settings.is_audible(Channel::Dmc));
+    }
+
+    #[test]
+    fn mute_wins_over_solo() {
+        let mut settings = ChannelSettings::default();
+        settings.set_solo(Channel::Noise, true);
+        settings.set_mute(Channel::Noise, true);
+        assert!(!settings.is_audible(Channel::Noise));
+        assert!(!settings.is_audible(Channel::Pulse2));
+    }
+
+    #[test]
+    fn half_gain_interpolates_between_table_entries() {
+        let mut mixer = Mixer::new(44100.0);
+        mixer.channel_settings.set_gain(Channel::Pulse1, 0.5);
+        let midpoint = (mixer.pulse_table[1] + mixer.pulse_table[2]) / 2.0;
+        assert!((mixer.mix(3, 0, 0, 0, 0) - midpoint).abs() < 1e-6);
+    }
+
+    #[test]
+    fn large_gain_clamps_to_table_end() {
+        let mut mixer = Mixer::new(44100.0);
+        mixer.channel_settings.set_gain(Channel::Pulse1, 10.0);
+        assert_eq!(mixer.mix(15, 0, 0, 0, 0), mixer.pulse_table[30]);
+    }
+}
@@ -1,12 +1,25 @@
+pub mod audio_sink;
+pub mod dmc;
+pub mod envelope;
+pub mod length_counter;
+pub mod mixer;
 pub mod noise;
 pub mod pulse;
+pub mod resampler;
+pub mod sweep;
 pub mod triangle;
 
+use crate::apu::dmc::Dmc;
+use crate::apu::mixer::{Channel, Mixer, OutputFilter};
 use crate::apu::noise::Noise;
 use crate::apu::pulse::Pulse;
+use crate::apu::resampler::Resampler;
 use crate::apu::triangle::Triangle;
+use crate::nes::region::Region;
 use serde::{Deserialize, Serialize};
 
+pub const SAMPLE_RATE: u32 = 44100;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChannelController {
     pub enable_pulse1: bool,
@@ -59,40 +72,101 @@ pub enum FrameMode {
     _5STEP,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStep {
+    Quarter,
+    Half,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FrameCounter {
     pub mode: FrameMode,
-    irq: bool,
-}
-
-impl Default for FrameCounter {
-    fn default() -> Self {
-        Self::new()
-    }
+    is_irq_inhibit: bool,
+    pub irq: bool,
+    cycle: u32,
+    reset_delay: Option<u8>,
+    region: Region,
 }
 
 impl FrameCounter {
-    fn new() -> Self {
+    fn new(region: Region) -> Self {
         Self {
             mode: FrameMode::_4STEP,
+            is_irq_inhibit: false,
             irq: false,
+            cycle: 0,
+            reset_delay: None,
+            region,
         }
     }
 
-    pub fn set(&mut self, data: u8) {
+    // The sequencer restarts 3 CPU cycles after a write made on an APU cycle and 4
+    // after one made between them.
+    pub fn set(&mut self, data: u8, is_odd_cycle: bool) {
         self.mode = match (data & 0b10000000) >> 7 {
             0 => FrameMode::_4STEP,
             1 => FrameMode::_5STEP,
             _ => unreachable!(),
         };
 
-        self.irq = ((data & 0b01000000) >> 6) != 0;
+        self.is_irq_inhibit = ((data & 0b01000000) >> 6) != 0;
+        if self.is_irq_inhibit {
+            self.irq = false;
+        }
+        self.reset_delay = Some(if is_odd_cycle { 3 } else { 4 });
+    }
+
+    fn is_5step(&self) -> bool {
+        matches!(self.mode, FrameMode::_5STEP)
+    }
+
+    pub fn clock(&mut self) -> Option<FrameStep> {
+        if let Some(delay) = self.reset_delay {
+            if delay > 1 {
+                self.reset_delay = Some(delay - 1);
+            } else {
+                self.reset_delay = None;
+                self.cycle = 0;
+                // Entering 5-step mode clocks every unit right away.
+                if self.is_5step() {
+                    return Some(FrameStep::Half);
+                }
+                return None;
+            }
+        }
+
+        let cycles = self.region.frame_step_cycles(self.is_5step());
+        self.cycle += 1;
+        let step = match cycles.iter().position(|cycle| *cycle == self.cycle) {
+            Some(0) | Some(2) => Some(FrameStep::Quarter),
+            Some(1) | Some(3) => Some(FrameStep::Half),
+            _ => None,
+        };
+        // The flag is raised on the three cycles around the end of a 4-step sequence.
+        if !self.is_5step() && !self.is_irq_inhibit && self.cycle + 1 >= cycles[3] {
+            self.irq = true;
+        }
+        if self.cycle >= cycles[4] {
+            self.cycle = 0;
+        }
+        step
     }
+}
 
-    pub fn get_envelop_count(&self) -> u8 {
-        match self.mode {
-            FrameMode::_4STEP => 60,
-            FrameMode::_5STEP => 48,
+// Resampled at the nominal rate so a recording's length follows the frame count.
+#[derive(Debug, Clone)]
+pub struct RecordTrack {
+    channel: Option<Channel>,
+    resampler: Resampler,
+    output_filter: OutputFilter,
+}
+
+impl RecordTrack {
+    fn new(channel: Option<Channel>, region: Region) -> Self {
+        Self {
+            channel,
+            resampler: Resampler::new(region.cpu_clock_hz() as f64, SAMPLE_RATE as f64),
+            output_filter: OutputFilter::new(SAMPLE_RATE as f32),
         }
     }
 }
@@ -103,14 +177,15 @@ pub struct APU {
     pub pulse2: Pulse,
     pub triangle: Triangle,
     pub noise: Noise,
+    pub dmc: Dmc,
     pub channel_controller: ChannelController,
     pub frame_counter: FrameCounter,
-}
-
-impl Default for APU {
-    fn default() -> Self {
-        Self::new()
-    }
+    pub mixer: Mixer,
+    resampler: Resampler,
+    #[serde(skip)]
+    record_tracks: Option<Vec<RecordTrack>>,
+    is_odd_cycle: bool,
+    pub region: Region,
 }
 
 impl APU {
@@ -119,21 +194,225 @@ impl APU {
         12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30, // 10-1F
     ];
 
-    pub fn new() -> Self {
-        let pulse1 = Pulse::default();
-        let pulse2 = Pulse::default();
+    pub fn new(region: Region) -> Self {
+        let pulse1 = Pulse::new(true);
+        let pulse2 = Pulse::new(false);
         let triangle = Triangle::default();
-        let noise = Noise::default();
+        let noise = Noise::new(region);
+        let dmc = Dmc::new(region);
         let channel_controller = ChannelController::default();
-        let frame_counter = FrameCounter::default();
+        let frame_counter = FrameCounter::new(region);
+        let mixer = Mixer::new(SAMPLE_RATE as f32);
+        let resampler = Resampler::new(region.cpu_clock_hz() as f64, SAMPLE_RATE as f64);
 
         Self {
             pulse1,
             pulse2,
             triangle,
             noise,
+            dmc,
             channel_controller,
             frame_counter,
+            mixer,
+            resampler,
+            record_tracks: None,
+            is_odd_cycle: false,
+            region,
+        }
+    }
+
+    // Reading acknowledges the frame IRQ.
+    pub fn status(&mut self) -> u8 {
+        let mut data = (self.frame_counter.irq as u8) << 6;
+        self.frame_counter.irq = false;
+        data |= self.pulse1.is_active() as u8;
+        data |= (self.pulse2.is_active() as u8) << 1;
+        data |= (self.triangle.is_active() as u8) << 2;
+        data |= (self.noise.is_active() as u8) << 3;
+        data |= (self.dmc.is_active() as u8) << 4;
+        data | (self.dmc.irq as u8) << 7
+    }
+
+    pub fn set_status(&mut self, data: u8) {
+        self.channel_controller.set(data);
+        self.pulse1
+            .set_enable(self.channel_controller.enable_pulse1);
+        self.pulse2
+            .set_enable(self.channel_controller.enable_pulse2);
+        self.triangle
+            .set_enable(self.channel_controller.enable_triangle);
+        self.noise.set_enable(self.channel_controller.enable_noise);
+        self.dmc.set_enable(self.channel_controller.enable_dmc);
+    }
+
+    pub fn set_sample_rate_ratio(&mut self, ratio: f64) {
+        self.resampler.set_rates(
+            self.region.cpu_clock_hz() as f64,
+            SAMPLE_RATE as f64 * ratio,
+        );
+    }
+
+    pub fn set_frame_counter(&mut self, data: u8) {
+        self.frame_counter.set(data, self.is_odd_cycle);
+    }
+
+    // Pulse timers tick on every other CPU cycle.
+    pub fn clock(&mut self) {
+        self.is_odd_cycle = !self.is_odd_cycle;
+        if self.is_odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        match self.frame_counter.clock() {
+            Some(FrameStep::Quarter) => self.clock_quarter_frame(),
+            Some(FrameStep::Half) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            None => (),
+        }
+        let levels = [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ];
+        let level = self
+            .mixer
+            .mix(levels[0], levels[1], levels[2], levels[3], levels[4]);
+        self.resampler.push(level);
+        if let Some(record_tracks) = &mut self.record_tracks {
+            for track in record_tracks.iter_mut() {
+                let level = match track.channel {
+                    Some(channel) => self.mixer.mix_channel(channel, levels[channel.index()]),
+                    None => level,
+                };
+                track.resampler.push(level);
+            }
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    pub fn read_samples(&mut self, samples: &mut Vec<f32>) {
+        let start = samples.len();
+        self.resampler.read(samples);
+        for sample in samples[start..].iter_mut() {
+            *sample = self.mixer.filter(*sample);
+        }
+    }
+
+    // The mixed track comes first, then each channel in Channel::ALL order.
+    pub fn start_record_tracks(&mut self, is_record_channels: bool) {
+        let region = self.region;
+        let mut record_tracks = vec![RecordTrack::new(None, region)];
+        if is_record_channels {
+            record_tracks.extend(
+                Channel::ALL
+                    .iter()
+                    .map(|channel| RecordTrack::new(Some(*channel), region)),
+            );
+        }
+        self.record_tracks = Some(record_tracks);
+    }
+
+    pub fn stop_record_tracks(&mut self) {
+        self.record_tracks = None;
+    }
+
+    pub fn read_record_samples(&mut self, track_samples: &mut [Vec<f32>]) {
+        let record_tracks = match &mut self.record_tracks {
+            Some(record_tracks) => record_tracks,
+            None => return,
+        };
+        for (track, samples) in record_tracks.iter_mut().zip(track_samples.iter_mut()) {
+            let start = samples.len();
+            track.resampler.read(samples);
+            for sample in samples[start..].iter_mut() {
+                *sample = track.output_filter.process(*sample);
+            }
         }
     }
+
+    pub fn is_irq(&self) -> bool {
+        self.dmc.irq || self.frame_counter.irq
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::apu::*;
+
+    fn clock_steps(frame_counter: &mut FrameCounter, cycles: u32) -> Vec<(u32, FrameStep)> {
+        (1..=cycles)
+            .filter_map(|cycle| frame_counter.clock().map(|step| (cycle, step)))
+            .collect()
+    }
+
+    #[test]
+    fn four_step_sequence_clocks_on_ntsc_cycles() {
+        let mut frame_counter = FrameCounter::new(Region::NTSC);
+        let steps = clock_steps(&mut frame_counter, 29830 * 2);
+        assert_eq!(
+            steps[..5],
+            [
+                (7457, FrameStep::Quarter),
+                (14913, FrameStep::Half),
+                (22371, FrameStep::Quarter),
+                (29829, FrameStep::Half),
+                (29830 + 7457, FrameStep::Quarter),
+            ]
+        );
+    }
+
+    #[test]
+    fn five_step_write_clocks_half_frame_after_delay() {
+        let mut frame_counter = FrameCounter::new(Region::NTSC);
+        frame_counter.set(0b10000000, true);
+        let steps = clock_steps(&mut frame_counter, 3 + 37282);
+        assert_eq!(
+            steps,
+            [
+                (3, FrameStep::Half),
+                (3 + 7457, FrameStep::Quarter),
+                (3 + 14913, FrameStep::Half),
+                (3 + 22371, FrameStep::Quarter),
+                (3 + 37281, FrameStep::Half),
+            ]
+        );
+        assert!(!frame_counter.irq);
+
+        let mut frame_counter = FrameCounter::new(Region::NTSC);
+        frame_counter.set(0b10000000, false);
+        assert_eq!(clock_steps(&mut frame_counter, 4), [(4, FrameStep::Half)]);
+    }
+
+    #[test]
+    fn four_step_sequence_raises_irq_around_end() {
+        let mut frame_counter = FrameCounter::new(Region::NTSC);
+        clock_steps(&mut frame_counter, 29827);
+        assert!(!frame_counter.irq);
+        clock_steps(&mut frame_counter, 1);
+        assert!(frame_counter.irq);
+
+        frame_counter.set(0b01000000, true);
+        assert!(!frame_counter.irq);
+        clock_steps(&mut frame_counter, 29830 * 2);
+        assert!(!frame_counter.irq);
+    }
 }
@@ -1,161 +1,39 @@
-use crate::apu::{FrameCounter, FrameMode, APU};
-use sdl2::audio::{AudioCallback, AudioDeviceLockGuard};
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::sweep::Sweep;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Sweep {
-    dividers_count: u8,
-    is_enable: bool,
-    dividers_period: u8,
-    is_negative: bool,
-    shift_count: u8,
-    reload_flag: bool,
-}
-
-impl Default for Sweep {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl Sweep {
-    fn new() -> Self {
-        Self {
-            dividers_count: 0,
-            is_enable: false,
-            dividers_period: 0,
-            is_negative: false,
-            shift_count: 0,
-            reload_flag: false,
-        }
-    }
-
-    pub fn addr(&mut self) -> u8 {
-        let mut data = 0;
-        data += (self.is_enable as u8) << 7;
-        data += self.dividers_period << 4;
-        data += (self.is_negative as u8) << 3;
-        data += self.shift_count;
-
-        data
-    }
-
-    pub fn set(&mut self, data: u8) {
-        self.dividers_count = 0;
-        self.is_enable = (data & 0b10000000) != 0;
-        self.dividers_period = (data & 0b01110000) >> 4;
-        self.dividers_count = self.dividers_period;
-        self.is_negative = (data & 0b00001000) != 0;
-        self.shift_count = data & 0b00000111;
-        self.reload_flag = true;
-    }
-
-    pub fn update(&mut self, timer: &mut u16) {
-        if self.is_enable && *timer > 8 && *timer < 0x7FF && self.dividers_count == 0 {
-            if self.is_negative {
-                *timer = *timer - (*timer >> self.shift_count);
-            } else {
-                *timer = *timer + (*timer >> self.shift_count);
-            }
-            if *timer <= 8 || *timer >= 0x7FF {
-                *timer = 0;
-            }
-        }
-
-        if self.dividers_count > 0 {
-            self.dividers_count -= 1;
-        } else if self.dividers_count == 0 && self.reload_flag {
-            self.dividers_count = self.dividers_period;
-            self.reload_flag = false;
-        }
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pulse {
     timer: u16,
-    current_timer: u16,
-    frame_counter: u8,
+    timer_counter: u16,
     sweep: Sweep,
-    clock_count: u16,
     length_counter_index: u8,
-    length_counter: u16,
-    current_volume: u8,
-    call_back_volume_buf: Vec<u8>,
-    envelope_divider: u8,
-    constant_volume_and_devider_period: u8,
-    phase: f32,
-    current_phase_inc: f32,
-    call_back_phase_inc_buf: Vec<f32>,
-    is_constant_volume: bool,
-    current_constant_volume: u8,
-    sequencer_count: u8,
+    length_counter: LengthCounter,
+    envelope: Envelope,
+    sequencer_step: u8,
     duty: u8,
-    call_back_duty_buf: Vec<u8>,
-    is_loop_envelope_and_counter_halt: bool,
-}
-
-impl Default for Pulse {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl AudioCallback for Pulse {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        if self.call_back_volume_buf.len() == 0 {
-            return;
-        }
-
-        for (i, x) in out.iter_mut().enumerate() {
-            if i >= self.call_back_volume_buf.len() {
-                break;
-            }
-            let duty = match self.call_back_duty_buf[i] {
-                0 => 0.875,
-                1 => 0.75,
-                2 => 0.50,
-                3 => 0.25,
-                _ => unreachable!(),
-            };
-            *x = if self.phase <= duty {
-                self.call_back_volume_buf[i] as f32 * 0.005
-            } else {
-                self.call_back_volume_buf[i] as f32 * (-0.005)
-            };
-            self.phase = (self.phase + self.call_back_phase_inc_buf[i]) % 1.0;
-        }
-        self.call_back_volume_buf = vec![];
-        self.call_back_phase_inc_buf = vec![];
-        self.call_back_duty_buf = vec![];
-    }
 }
 
 impl Pulse {
-    fn new() -> Self {
+    const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+        [0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 1, 1, 0, 0, 0, 0, 0],
+        [0, 1, 1, 1, 1, 0, 0, 0],
+        [1, 0, 0, 1, 1, 1, 1, 1],
+    ];
+
+    // Pulse 1 passes true to negate its sweep with ones' complement.
+    pub fn new(is_ones_complement: bool) -> Self {
         Self {
             timer: 0,
-            current_timer: 0,
-            frame_counter: 0,
-            sweep: Sweep::default(),
-            clock_count: 0,
-            envelope_divider: 15,
-            call_back_volume_buf: [].to_vec(),
-            current_volume: 0,
+            timer_counter: 0,
+            sweep: Sweep::new(is_ones_complement),
             length_counter_index: 0,
-            length_counter: 0,
-            constant_volume_and_devider_period: 0,
-            phase: 0.0,
-            current_phase_inc: 0.0,
-            call_back_phase_inc_buf: [].to_vec(),
-            is_constant_volume: false,
-            current_constant_volume: 0,
-            sequencer_count: 0,
+            length_counter: LengthCounter::default(),
+            envelope: Envelope::default(),
+            sequencer_step: 0,
             duty: 0,
-            call_back_duty_buf: [].to_vec(),
-            is_loop_envelope_and_counter_halt: false,
         }
     }
 
@@ -164,9 +42,7 @@ impl Pulse {
         match addr {
             0 => {
                 n += self.duty << 6;
-                n += (self.is_loop_envelope_and_counter_halt as u8) << 5;
-                n += (self.is_constant_volume as u8) << 4;
-                n += self.constant_volume_and_devider_period;
+                n += self.envelope.addr();
                 n
             }
             1 => self.sweep.addr(),
@@ -176,7 +52,7 @@ impl Pulse {
             }
             3 => {
                 n += ((self.timer & 0b11100000000) >> 8) as u8;
-                n += ((self.length_counter_index & 0b11111000) >> 3) as u8;
+                n += self.length_counter_index << 3;
                 n
             }
 
@@ -196,132 +72,56 @@ impl Pulse {
         match addr {
             0 => {
                 self.duty = (data & 0b11000000) >> 6;
-                self.is_loop_envelope_and_counter_halt = (data & 0b00100000) != 0;
-                self.is_constant_volume = (data & 0b00010000) != 0;
-                self.current_constant_volume = 0xF;
-                self.constant_volume_and_devider_period = data & 0b00001111;
+                self.length_counter.set_halt((data & 0b00100000) != 0);
+                self.envelope.set(data);
             }
             2 => {
                 self.timer &= 0x700;
                 self.timer |= data as u16;
-                self.current_timer = self.timer;
             }
             3 => {
                 self.timer &= 0xFF;
                 self.timer |= (data as u16 & 0b00000111) << 8;
-                self.current_timer = self.timer;
                 self.length_counter_index = (data & 0b11111000) >> 3;
-                self.length_counter = APU::LENGTH_COUNTER[self.length_counter_index as usize];
-                self.sequencer_count = self.constant_volume_and_devider_period;
+                self.length_counter.load(self.length_counter_index);
+                self.sequencer_step = 0;
+                self.envelope.restart();
             }
             _ => unimplemented!(),
         }
     }
 
-    fn get_volume(&self) -> u8 {
-        if self.is_constant_volume {
-            self.constant_volume_and_devider_period
-        } else {
-            self.current_constant_volume
-        }
+    pub fn set_enable(&mut self, is_enable: bool) {
+        self.length_counter.set_enable(is_enable);
     }
 
-    fn update_constant_volume(&mut self) {
-        if self.current_constant_volume > 0 {
-            self.current_constant_volume -= 1;
-        }
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
     }
 
-    fn update_envelop(&mut self) {
-        if self.envelope_divider > 0 {
-            self.envelope_divider -= 1
+    pub fn clock_timer(&mut self) {
+        if self.timer_counter == 0 {
+            self.timer_counter = self.timer;
+            self.sequencer_step = (self.sequencer_step + 1) % 8;
         } else {
-            self.envelope_divider = 15;
-
-            if self.constant_volume_and_devider_period > 0 {
-                self.constant_volume_and_devider_period -= 1;
-            }
+            self.timer_counter -= 1;
         }
-        self.update_constant_volume();
     }
 
-    fn update_length_counter(&mut self) {
-        if self.length_counter > 0 {
-            self.length_counter -= 1;
-        }
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
     }
 
-    fn update_5step_frame(&mut self) {
-        if self.frame_counter == 39
-            || self.frame_counter == 78
-            || self.frame_counter == 117
-            || self.frame_counter == 156
-            || self.frame_counter == 192
-        {
-            self.update_envelop();
-        }
-        if self.frame_counter == 78 || self.frame_counter == 192 {
-            self.update_length_counter();
-            self.sweep.update(&mut self.current_timer);
-        }
-        if self.frame_counter >= 192 {
-            self.frame_counter = 0;
-        }
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        self.sweep.clock(&mut self.timer);
     }
 
-    fn update_4step_frame(&mut self) {
-        if self.frame_counter == 60
-            || self.frame_counter == 120
-            || self.frame_counter == 180
-            || self.frame_counter == 240
-        {
-            self.update_envelop();
-        }
-        if self.frame_counter == 120 || self.frame_counter == 240 {
-            self.update_length_counter();
-            self.sweep.update(&mut self.current_timer);
-        }
-        if self.frame_counter >= 240 {
-            self.frame_counter = 0;
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.is_active() || self.sweep.is_muting(self.timer) {
+            return 0;
         }
-    }
-
-    fn insert_callback(&mut self, lock: &mut AudioDeviceLockGuard<Self>) {
-        (*lock).call_back_volume_buf.push(self.current_volume);
-        (*lock).call_back_phase_inc_buf.push(self.current_phase_inc);
-        (*lock).call_back_duty_buf.push(self.duty);
-    }
-
-    fn is_signal_enable(&self, is_enable: &bool) -> bool {
-        *is_enable
-            && (self.length_counter > 0 || self.is_loop_envelope_and_counter_halt)
-            && self.current_timer >= 8
-    }
-
-    pub fn update(
-        &mut self,
-        frame_counter: &mut FrameCounter,
-        is_enable: &mut bool,
-        lock: &mut AudioDeviceLockGuard<Self>,
-    ) {
-        if self.is_signal_enable(&is_enable) {
-            (*lock).clock_count += 1;
-            if (*lock).clock_count >= 240 {
-                (*lock).clock_count -= 240;
-                self.frame_counter += 1;
-                match frame_counter.mode {
-                    FrameMode::_4STEP => self.update_4step_frame(),
-                    FrameMode::_5STEP => self.update_5step_frame(),
-                }
-
-                self.current_volume = self.get_volume();
-                self.current_phase_inc =
-                    (1789773.0 / ((16.0 * self.current_timer as f32) + 1.0)) / 44100 as f32;
-            }
-        } else {
-            self.current_volume = 0;
-            self.current_phase_inc = 0.0;
-        };
-        self.insert_callback(lock);
+        Self::DUTY_SEQUENCES[self.duty as usize][self.sequencer_step as usize]
+            * self.envelope.output()
     }
 }
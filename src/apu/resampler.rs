@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+const KERNEL_TAPS: usize = 16;
+const KERNEL_PHASES: usize = 32;
+const KERNEL_CUTOFF: f64 = 0.45;
+
+// Each change of level is added as a windowed-sinc step at its fractional position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resampler {
+    samples_per_clock: f64,
+    time: f64,
+    last_input: f32,
+    accumulator: f32,
+    deltas: Vec<f32>,
+    #[serde(skip, default = "Resampler::build_kernel")]
+    kernel: Vec<[f32; KERNEL_TAPS]>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            samples_per_clock: sample_rate / clock_rate,
+            time: 0.0,
+            last_input: 0.0,
+            accumulator: 0.0,
+            deltas: vec![0.0; KERNEL_TAPS],
+            kernel: Self::build_kernel(),
+        }
+    }
+
+    fn build_kernel() -> Vec<[f32; KERNEL_TAPS]> {
+        (0..KERNEL_PHASES)
+            .map(|phase| {
+                let frac = phase as f64 / KERNEL_PHASES as f64;
+                let mut taps = [0.0; KERNEL_TAPS];
+                for (k, tap) in taps.iter_mut().enumerate() {
+                    let x = k as f64 - frac - (KERNEL_TAPS / 2) as f64 + 1.0;
+                    let t = x * 2.0 * KERNEL_CUTOFF;
+                    let sinc = if t == 0.0 {
+                        1.0
+                    } else {
+                        (PI * t).sin() / (PI * t)
+                    };
+                    let n = (x + (KERNEL_TAPS / 2) as f64) / KERNEL_TAPS as f64;
+                    let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+                    *tap = (sinc * window) as f32;
+                }
+                let sum: f32 = taps.iter().sum();
+                taps.iter_mut().for_each(|tap| *tap /= sum);
+                taps
+            })
+            .collect()
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.samples_per_clock = sample_rate / clock_rate;
+    }
+
+    pub fn push(&mut self, input: f32) {
+        if input != self.last_input {
+            self.add_delta(input - self.last_input);
+            self.last_input = input;
+        }
+        self.time += self.samples_per_clock;
+    }
+
+    fn add_delta(&mut self, delta: f32) {
+        let offset = self.time as usize;
+        let phase = ((self.time - offset as f64) * KERNEL_PHASES as f64) as usize;
+        if self.deltas.len() < offset + KERNEL_TAPS {
+            self.deltas.resize(offset + KERNEL_TAPS, 0.0);
+        }
+        for (n, tap) in self.kernel[phase].iter().enumerate() {
+            self.deltas[offset + n] += delta * tap;
+        }
+    }
+
+    pub fn read(&mut self, samples: &mut Vec<f32>) {
+        let count = self.time as usize;
+        if count == 0 {
+            return;
+        }
+        if self.deltas.len() < count + KERNEL_TAPS {
+            self.deltas.resize(count + KERNEL_TAPS, 0.0);
+        }
+        for delta in self.deltas.drain(..count) {
+            self.accumulator += delta;
+            samples.push(self.accumulator);
+        }
+        self.time -= count as f64;
+    }
+}
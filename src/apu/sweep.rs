@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sweep {
+    dividers_count: u8,
+    is_enable: bool,
+    dividers_period: u8,
+    is_negative: bool,
+    shift_count: u8,
+    reload_flag: bool,
+    is_ones_complement: bool,
+}
+
+impl Sweep {
+    pub fn new(is_ones_complement: bool) -> Self {
+        Self {
+            dividers_count: 0,
+            is_enable: false,
+            dividers_period: 0,
+            is_negative: false,
+            shift_count: 0,
+            reload_flag: false,
+            is_ones_complement,
+        }
+    }
+
+    pub fn addr(&mut self) -> u8 {
+        let mut data = 0;
+        data += (self.is_enable as u8) << 7;
+        data += self.dividers_period << 4;
+        data += (self.is_negative as u8) << 3;
+        data += self.shift_count;
+
+        data
+    }
+
+    pub fn set(&mut self, data: u8) {
+        self.is_enable = (data & 0b10000000) != 0;
+        self.dividers_period = (data & 0b01110000) >> 4;
+        self.is_negative = (data & 0b00001000) != 0;
+        self.shift_count = data & 0b00000111;
+        self.reload_flag = true;
+    }
+
+    fn target_period(&self, period: u16) -> u16 {
+        let change = period >> self.shift_count;
+        if !self.is_negative {
+            period + change
+        } else if self.is_ones_complement {
+            period.saturating_sub(change + 1)
+        } else {
+            period.saturating_sub(change)
+        }
+    }
+
+    // Mutes even while the sweep itself is disabled.
+    pub fn is_muting(&self, period: u16) -> bool {
+        period < 8 || self.target_period(period) > 0x7FF
+    }
+
+    pub fn clock(&mut self, period: &mut u16) {
+        if self.dividers_count == 0
+            && self.is_enable
+            && self.shift_count > 0
+            && !self.is_muting(*period)
+        {
+            *period = self.target_period(*period);
+        }
+
+        if self.dividers_count == 0 || self.reload_flag {
+            self.dividers_count = self.dividers_period;
+            self.reload_flag = false;
+        } else {
+            self.dividers_count -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::apu::sweep::*;
+
+    fn sweep(is_ones_complement: bool, data: u8) -> Sweep {
+        let mut sweep = Sweep::new(is_ones_complement);
+        sweep.set(data);
+        sweep
+    }
+
+    #[test]
+    fn target_period_negates_with_ones_or_twos_complement() {
+        // Enabled, negate, shift 1.
+        assert_eq!(sweep(true, 0b10001001).target_period(0x100), 0x7F);
+        assert_eq!(sweep(false, 0b10001001).target_period(0x100), 0x80);
+        assert_eq!(sweep(true, 0b10000001).target_period(0x100), 0x180);
+        assert_eq!(sweep(false, 0b10000001).target_period(0x100), 0x180);
+    }
+
+    #[test]
+    fn is_muting_on_short_period_or_overflowing_target_even_when_disabled() {
+        let sweep = sweep(false, 0b00000001);
+        assert!(sweep.is_muting(7));
+        assert!(!sweep.is_muting(8));
+        assert!(!sweep.is_muting(0x554));
+        assert!(sweep.is_muting(0x556));
+    }
+
+    #[test]
+    fn clock_updates_period_when_divider_reaches_zero() {
+        // Enabled, divider period 1, shift 1.
+        let mut sweep = sweep(false, 0b10010001);
+        let mut period = 0x100;
+        sweep.clock(&mut period);
+        assert_eq!(period, 0x180);
+        sweep.clock(&mut period);
+        assert_eq!(period, 0x180);
+        sweep.clock(&mut period);
+        assert_eq!(period, 0x240);
+    }
+}
@@ -1,25 +1,16 @@
-use crate::apu::{FrameCounter, FrameMode, APU};
-use sdl2::audio::{AudioCallback, AudioDeviceLockGuard};
+use crate::apu::length_counter::LengthCounter;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Triangle {
     timer: u16,
-    current_timer: u16,
-    frame_counter: u8,
-    clock_count: u16,
+    timer_counter: u16,
     controll_flag: bool,
-    length_counter_halt_or_linear_counter_control: bool,
+    is_linear_counter_reload: bool,
     linear_counter_load: u8,
     linear_counter: u8,
-    linear_phase: f32,
-    call_back_linear_phase_buf: Vec<f32>,
-    linear_inc_phase: bool,
-    call_back_phase_inc_buf: Vec<f32>,
-    length_counter_index: u8,
-    length_counter: u16,
-    current_phase_inc: f32,
-    is_loop_envelope_and_counter_halt: bool,
+    sequencer_step: u8,
+    length_counter: LengthCounter,
 }
 
 impl Default for Triangle {
@@ -28,181 +19,143 @@ impl Default for Triangle {
     }
 }
 
-impl AudioCallback for Triangle {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        if self.call_back_phase_inc_buf.len() == 0 {
-            return;
-        }
-
-        for (i, x) in out.iter_mut().enumerate() {
-            if i >= self.call_back_phase_inc_buf.len() {
-                break;
-            }
-            *x = if self.linear_phase <= 0.5 {
-                self.call_back_linear_phase_buf[i] * 0.003
-            } else {
-                -self.call_back_linear_phase_buf[i] * 0.003
-            };
-            self.linear_phase = (self.linear_phase + self.call_back_phase_inc_buf[i]) % 1.0;
-        }
-        self.call_back_linear_phase_buf = [].to_vec();
-        self.call_back_phase_inc_buf = [].to_vec();
-    }
-}
-
 impl Triangle {
+    const SEQUENCE: [u8; 32] = [
+        15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, //
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    ];
+
     fn new() -> Self {
         Self {
             timer: 0,
-            current_timer: 0,
-            frame_counter: 0,
-            clock_count: 0,
+            timer_counter: 0,
             controll_flag: false,
-            length_counter_halt_or_linear_counter_control: false,
+            is_linear_counter_reload: false,
             linear_counter_load: 0,
             linear_counter: 0,
-            linear_phase: 0.0,
-            call_back_linear_phase_buf: vec![],
-            linear_inc_phase: true,
-            length_counter_index: 0,
-            length_counter: 0,
-            current_phase_inc: 0.0,
-            call_back_phase_inc_buf: vec![],
-            is_loop_envelope_and_counter_halt: false,
+            sequencer_step: 0,
+            length_counter: LengthCounter::default(),
         }
     }
 
-    fn update_liner_phase(&mut self) {
-        match self.linear_inc_phase {
-            true => {
-                if self.linear_phase < 16.0 {
-                    self.linear_phase += 1.0;
-                } else {
-                    self.linear_inc_phase = false;
-                }
-            }
-            false => {
-                if self.linear_phase > -16.0 {
-                    self.linear_phase -= 1.0;
-                } else {
-                    self.linear_inc_phase = true;
-                }
-            }
+    fn update_linear_counter(&mut self) {
+        if self.is_linear_counter_reload {
+            self.linear_counter = self.linear_counter_load;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
         }
-    }
 
-    fn update_linear_counter(&mut self) {
         if !self.controll_flag {
-            self.length_counter_halt_or_linear_counter_control = false;
+            self.is_linear_counter_reload = false;
         }
+    }
 
-        if self.length_counter_halt_or_linear_counter_control {
-            self.linear_counter = self.linear_counter_load;
-        }
+    pub fn set_enable(&mut self, is_enable: bool) {
+        self.length_counter.set_enable(is_enable);
+    }
 
-        if self.linear_counter > 0 {
-            self.linear_counter -= 1;
-            self.update_liner_phase();
-        }
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
     }
 
-    fn update_length_counter(&mut self) {
-        if self.length_counter > 0 {
-            self.length_counter -= 1
+    // Periods below 2 are ultrasonic, so the sequencer holds there as it does when a
+    // counter runs out.
+    pub fn clock_timer(&mut self) {
+        if self.timer_counter == 0 {
+            self.timer_counter = self.timer;
+            if self.length_counter.is_active() && self.linear_counter > 0 && self.timer >= 2 {
+                self.sequencer_step = (self.sequencer_step + 1) % 32;
+            }
+        } else {
+            self.timer_counter -= 1;
         }
     }
 
-    fn update_5step_frame(&mut self) {
-        if self.frame_counter == 39
-            || self.frame_counter == 78
-            || self.frame_counter == 117
-            || self.frame_counter == 156
-            || self.frame_counter == 192
-        {
-            self.update_linear_counter();
-        }
-        if self.frame_counter == 78 || self.frame_counter == 192 {
-            self.update_length_counter();
-        }
-        if self.frame_counter >= 192 {
-            self.frame_counter = 0;
-        }
+    pub fn clock_quarter_frame(&mut self) {
+        self.update_linear_counter();
     }
 
-    fn update_4step_frame(&mut self) {
-        if self.frame_counter == 60
-            || self.frame_counter == 120
-            || self.frame_counter == 180
-            || self.frame_counter == 240
-        {
-            self.update_linear_counter();
-        }
-        if self.frame_counter == 120 || self.frame_counter == 240 {
-            self.update_length_counter();
-        }
-        if self.frame_counter >= 240 {
-            self.frame_counter = 0;
-        }
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
     }
 
-    fn insert_callback(&mut self, lock: &mut AudioDeviceLockGuard<Self>) {
-        (*lock).call_back_linear_phase_buf.push(self.linear_phase);
-        (*lock).call_back_phase_inc_buf.push(self.current_phase_inc);
-    }
-
-    fn is_signal_enable(&self, is_enable: &bool) -> bool {
-        *is_enable
-            && (self.length_counter > 0 || self.is_loop_envelope_and_counter_halt)
-            && self.current_timer >= 8
-    }
-
-    pub fn update(
-        &mut self,
-        frame_counter: &mut FrameCounter,
-        is_enable: &mut bool,
-        lock: &mut AudioDeviceLockGuard<Self>,
-    ) {
-        if self.is_signal_enable(&is_enable) {
-            (*lock).clock_count += 1;
-            if (*lock).clock_count >= 240 {
-                (*lock).clock_count -= 240;
-                self.frame_counter += 1;
-                match frame_counter.mode {
-                    FrameMode::_4STEP => self.update_4step_frame(),
-                    FrameMode::_5STEP => self.update_5step_frame(),
-                }
-            }
-            self.current_phase_inc =
-                (1789773.0 / ((32.0 * self.current_timer as f32) + 1.0)) / 44100 as f32;
-        } else {
-            self.linear_phase = 0.0;
-        };
-        self.insert_callback(lock);
+    pub fn output(&self) -> u8 {
+        Self::SEQUENCE[self.sequencer_step as usize]
     }
 
     pub fn set(&mut self, addr: u8, data: u8) {
         match addr {
             0 => {
-                self.length_counter_halt_or_linear_counter_control = (data & 0b10000000) != 0;
                 self.linear_counter_load = data & 0b01111111;
                 self.controll_flag = (data & 0b10000000) != 0;
+                self.length_counter.set_halt(self.controll_flag);
             }
             2 => {
                 self.timer &= 0x700;
                 self.timer |= data as u16;
-                self.current_timer = self.timer;
             }
             3 => {
                 self.timer &= 0xFF;
                 self.timer |= (data as u16 & 0b00000111) << 8;
-                self.current_timer = self.timer;
-                self.length_counter_index = (data & 0b11111000) >> 3;
-                self.length_counter = APU::LENGTH_COUNTER[self.length_counter_index as usize];
-                self.length_counter_halt_or_linear_counter_control = true;
+                self.length_counter.load((data & 0b11111000) >> 3);
+                self.is_linear_counter_reload = true;
             }
             _ => unreachable!(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::apu::triangle::*;
+
+    fn playing_triangle(timer: u16) -> Triangle {
+        let mut triangle = Triangle::new();
+        triangle.set_enable(true);
+        triangle.set(0, 0b01111111);
+        triangle.set(2, timer as u8);
+        triangle.set(3, 0b00001000 | (timer >> 8) as u8);
+        triangle.clock_quarter_frame();
+        triangle
+    }
+
+    fn clock_timer(triangle: &mut Triangle, cycles: usize) {
+        (0..cycles).for_each(|_| triangle.clock_timer());
+    }
+
+    #[test]
+    fn steps_once_per_timer_period() {
+        let mut triangle = playing_triangle(4);
+        clock_timer(&mut triangle, 10);
+        assert_eq!(triangle.sequencer_step, 2);
+        assert_eq!(triangle.output(), 13);
+    }
+
+    #[test]
+    fn holds_while_linear_counter_is_zero() {
+        let mut triangle = playing_triangle(4);
+        triangle.set(0, 0);
+        triangle.set(3, 0b00001000);
+        triangle.clock_quarter_frame();
+        clock_timer(&mut triangle, 10);
+        assert_eq!(triangle.sequencer_step, 0);
+        assert_eq!(triangle.output(), 15);
+    }
+
+    #[test]
+    fn holds_while_length_counter_is_zero() {
+        let mut triangle = playing_triangle(4);
+        triangle.set_enable(false);
+        clock_timer(&mut triangle, 10);
+        assert_eq!(triangle.sequencer_step, 0);
+        assert_eq!(triangle.output(), 15);
+    }
+
+    #[test]
+    fn holds_at_ultrasonic_periods() {
+        let mut triangle = playing_triangle(1);
+        clock_timer(&mut triangle, 10);
+        assert_eq!(triangle.sequencer_step, 0);
+        assert_eq!(triangle.output(), 15);
+    }
+}
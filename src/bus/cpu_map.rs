@@ -76,7 +76,63 @@ impl InternalRegisters {
     }
 
     fn inc_vram_addr(&mut self, data: u16) {
-        self.current_vram += data;
+        self.current_vram = (self.current_vram + data) & 0x7FFF;
+    }
+
+    pub fn inc_coarse_x(&mut self) {
+        if self.current_vram & 0x001F == 0x001F {
+            self.current_vram &= !0x001F;
+            self.current_vram ^= 0x0400;
+        } else {
+            self.current_vram += 1;
+        }
+    }
+
+    pub fn inc_y(&mut self) {
+        if self.current_vram & 0x7000 != 0x7000 {
+            self.current_vram += 0x1000;
+        } else {
+            self.current_vram &= !0x7000;
+            let coarse_y = match (self.current_vram & 0x03E0) >> 5 {
+                29 => {
+                    self.current_vram ^= 0x0800;
+                    0
+                }
+                31 => 0,
+                n => n + 1,
+            };
+            self.current_vram = (self.current_vram & !0x03E0) | (coarse_y << 5);
+        }
+    }
+
+    pub fn copy_horizontal_bits(&mut self) {
+        self.current_vram = (self.current_vram & !0x041F) | (self.temporary_vram & 0x041F);
+    }
+
+    pub fn copy_vertical_bits(&mut self) {
+        self.current_vram = (self.current_vram & !0x7BE0) | (self.temporary_vram & 0x7BE0);
+    }
+
+    pub fn fine_y(&self) -> u16 {
+        (self.current_vram >> 12) & 0b111
+    }
+
+    pub fn tile_addr(&self) -> u16 {
+        0x2000 | (self.current_vram & 0x0FFF)
+    }
+
+    pub fn attr_addr(&self) -> u16 {
+        let v = self.current_vram;
+        0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07)
+    }
+
+    pub fn attr_shift(&self) -> u16 {
+        let v = self.current_vram;
+        ((v >> 4) & 0b100) | (v & 0b010)
+    }
+
+    pub fn is_latched(&self) -> bool {
+        self.latch_flag
     }
 
     fn copy_current_vram_to_tempolary_vram(&mut self) {
@@ -93,6 +149,7 @@ pub struct PpuRegister {
     oam_data: u8,
     ppu_data: u8,
     pub ppu_buffer: PpuBuffer,
+    pub open_bus: OpenBus,
     pub internal_registers: InternalRegisters,
 }
 
@@ -136,23 +193,15 @@ impl PpuCtrl {
         self.base_name_table_addr = data & 0b00000011;
     }
 
-    fn to_n(&self) -> u8 {
-        let mut data = 0;
-        data += self.gen_nmi as u8 * 0b10000000;
-        data += self.ppu_selector as u8 * 0b01000000;
-        data += self.sprite_size as u8 * 0b00100000;
-        data += self.bk_table_addr as u8 * 0b00010000;
-        data += self.sprite_ptn_table_addr as u8 * 0b00001000;
-        data += self.vram_increment as u8 * 0b00000100;
-        data += self.base_name_table_addr;
-        data
-    }
-
     pub fn is_deep_bk_index(&self) -> bool {
         self.bk_table_addr
     }
 
-    fn increment_vram_num(&mut self) -> u16 {
+    pub fn is_ppu_selector(&self) -> bool {
+        self.ppu_selector
+    }
+
+    pub fn increment_vram_num(&self) -> u16 {
         match self.vram_increment {
             false => 1,
             true => 32,
@@ -162,6 +211,13 @@ impl PpuCtrl {
     pub fn for_big(&self) -> bool {
         self.sprite_size
     }
+
+    pub fn sprite_height(&self) -> u8 {
+        match self.for_big() {
+            false => 8,
+            true => 16,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -207,25 +263,25 @@ impl PpuMask {
         self.gray_scale = (data & 0b00000001) != 0;
     }
 
-    fn to_n(&self) -> u8 {
-        let mut data = 0;
-        data += self.emf_blue as u8 * 0b10000000;
-        data += self.emf_green as u8 * 0b01000000;
-        data += self.emf_red as u8 * 0b00100000;
-        data += self.show_sprites as u8 * 0b00010000;
-        data += self.show_background as u8 * 0b00001000;
-        data += self.show_sprites_in_leftmost as u8 * 0b00000100;
-        data += self.show_background_in_leftmost as u8 * 0b00000010;
-        data += self.gray_scale as u8;
-        data
-    }
-
     pub fn apply_gray_scale(&self, color_idx: &mut usize) {
         if self.gray_scale {
             *color_idx &= 0b11110000
         }
     }
 
+    pub fn emphasis(&self) -> usize {
+        let mut data = 0;
+        data += self.emf_red as usize;
+        data += (self.emf_green as usize) << 1;
+        data += (self.emf_blue as usize) << 2;
+        data
+    }
+
+    // Emphasis bits select one of the eight 64-colour banks of the 512-entry palette.
+    pub fn apply_emphasis(&self, color_idx: &mut usize) {
+        *color_idx |= self.emphasis() << 6;
+    }
+
     pub fn is_show_sprites(&self) -> bool {
         self.show_sprites
     }
@@ -233,6 +289,34 @@ impl PpuMask {
     pub fn is_show_background(&self) -> bool {
         self.show_background
     }
+
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.show_background || self.show_sprites
+    }
+
+    pub fn is_gray_scale(&self) -> bool {
+        self.gray_scale
+    }
+
+    pub fn is_show_sprites_in_leftmost(&self) -> bool {
+        self.show_sprites_in_leftmost
+    }
+
+    pub fn is_show_background_in_leftmost(&self) -> bool {
+        self.show_background_in_leftmost
+    }
+
+    pub fn is_show_sprites_at(&self, x: u8) -> bool {
+        x >= 8 || self.show_sprites_in_leftmost
+    }
+
+    pub fn is_show_background_at(&self, x: u8) -> bool {
+        x >= 8 || self.show_background_in_leftmost
+    }
+
+    pub fn is_clipping_leftmost(&self) -> bool {
+        !self.show_sprites_in_leftmost || !self.show_background_in_leftmost
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -240,8 +324,6 @@ pub struct PpuStatus {
     pub in_vlank: bool,
     sprite_zero_hit: bool,
     sprite_evoluation: bool,
-    bus: u8,
-    pub line_occured_sprite_zero_hit: u16,
 }
 
 impl Default for PpuStatus {
@@ -256,23 +338,14 @@ impl PpuStatus {
             in_vlank: false,
             sprite_zero_hit: false,
             sprite_evoluation: false,
-            bus: 0,
-            line_occured_sprite_zero_hit: 0,
         }
     }
-    fn set(&mut self, data: u8) {
-        self.in_vlank = (data & 0b10000000) != 0;
-        self.sprite_zero_hit = (data & 0b01000000) != 0;
-        self.sprite_evoluation = (data & 0b00100000) != 0;
-        self.bus = data & 0b00001111;
-    }
 
     fn to_n(&self) -> u8 {
         let mut data = 0;
         data += self.in_vlank as u8 * 0b10000000;
         data += self.sprite_zero_hit as u8 * 0b01000000;
         data += self.sprite_evoluation as u8 * 0b00100000;
-        data += self.bus;
         data
     }
 
@@ -280,10 +353,6 @@ impl PpuStatus {
         self.sprite_zero_hit
     }
 
-    pub fn set_line_occured_sprite_zero_hit(&mut self, line: u16) {
-        self.line_occured_sprite_zero_hit = line;
-    }
-
     pub fn true_sprite_zero_hit(&mut self) {
         self.sprite_zero_hit = true;
     }
@@ -291,12 +360,69 @@ impl PpuStatus {
     pub fn false_sprite_zero_hit(&mut self) {
         self.sprite_zero_hit = false;
     }
+
+    pub fn is_sprite_overflow(&self) -> bool {
+        self.sprite_evoluation
+    }
+
+    pub fn true_sprite_overflow(&mut self) {
+        self.sprite_evoluation = true;
+    }
+
+    pub fn false_sprite_overflow(&mut self) {
+        self.sprite_evoluation = false;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenBus {
+    data: u8,
+    refreshed_frames: [u64; 8],
+}
+
+impl Default for OpenBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenBus {
+    // Bits of the I/O latch fade to 0 after roughly 600ms without being driven.
+    const DECAY_FRAMES: u64 = 36;
+
+    fn new() -> Self {
+        Self {
+            data: 0,
+            refreshed_frames: [0; 8],
+        }
+    }
+
+    pub fn addr(&mut self, frame: u64) -> u8 {
+        for (bit, refreshed_frame) in self.refreshed_frames.iter().enumerate() {
+            if frame.saturating_sub(*refreshed_frame) >= Self::DECAY_FRAMES {
+                self.data &= !(1 << bit);
+            }
+        }
+        self.data
+    }
+
+    pub fn set(&mut self, data: u8, frame: u64) {
+        self.set_bits(data, 0b11111111, frame);
+    }
+
+    pub fn set_bits(&mut self, data: u8, mask: u8, frame: u64) {
+        self.data = (self.data & !mask) | (data & mask);
+        for (bit, refreshed_frame) in self.refreshed_frames.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                *refreshed_frame = frame;
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PpuBuffer {
     pub buffer: u8,
-    pub vram_memory: u8,
 }
 
 impl Default for PpuBuffer {
@@ -307,20 +433,15 @@ impl Default for PpuBuffer {
 
 impl PpuBuffer {
     fn new() -> Self {
-        PpuBuffer {
-            buffer: 0,
-            vram_memory: 0,
-        }
+        PpuBuffer { buffer: 0 }
     }
 
-    pub fn addr(&mut self) -> u8 {
-        let data = self.buffer;
-        self.buffer = self.vram_memory;
-        data
+    pub fn addr(&self) -> u8 {
+        self.buffer
     }
 
     pub fn set(&mut self, data: u8) {
-        self.vram_memory = data;
+        self.buffer = data;
     }
 }
 
@@ -340,6 +461,7 @@ impl PpuRegister {
             oam_data: 0,
             ppu_data: 0,
             ppu_buffer: PpuBuffer::default(),
+            open_bus: OpenBus::default(),
             internal_registers: InternalRegisters::default(),
         }
     }
@@ -352,7 +474,7 @@ impl PpuRegister {
                 self.ppu_ctrl.set(data);
             }
             0x2001 => self.ppu_mask.set(data),
-            0x2002 => self.ppu_status.set(data),
+            0x2002 => (),
             0x2003 => self.oam_addr = data,
             0x2004 => self.oam_data = data,
             0x2005 => {
@@ -397,19 +519,27 @@ impl PpuRegister {
 
     fn addr(&mut self, addr: u16) -> u8 {
         match addr {
-            0x2000 => self.ppu_ctrl.to_n(),
-            0x2001 => self.ppu_mask.to_n(),
             0x2002 => self.ppu_status.to_n(),
-            0x2003 => self.oam_addr,
             0x2004 => self.oam_data,
             _ => unreachable!(),
         }
     }
 
+    pub fn oam_addr(&self) -> u8 {
+        self.oam_addr
+    }
+
     pub fn constant_inc_vram(&mut self) {
         let data = self.ppu_ctrl.increment_vram_num();
         self.internal_registers.inc_vram_addr(data);
     }
+
+    // Accessing $2007 while rendering bumps coarse X and Y together instead of
+    // the configured 1/32 step.
+    pub fn rendering_inc_vram(&mut self) {
+        self.internal_registers.inc_coarse_x();
+        self.internal_registers.inc_y();
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -461,8 +591,6 @@ pub struct CpuMap {
     #[serde_as(as = "[_; 0x1800]")]
     wram_mirror: [u8; 0x1800],
     pub ppu_register: PpuRegister,
-    #[serde_as(as = "[_; 0x1FF8]")]
-    ppu_register_mirror: [u8; 0x1FF8],
     rp2a03: RP2A03,
     func_apu_io: [u8; 0x0008],
     #[serde_as(as = "[_; 0x1FE0]")]
@@ -487,7 +615,6 @@ impl CpuMap {
             wram: [0; 0x0800],
             wram_mirror: [0; 0x1800],
             ppu_register: PpuRegister::default(),
-            ppu_register_mirror: [0; 0x1FF8],
             rp2a03: RP2A03::default(),
             func_apu_io: [0; 0x0008],
             erom: [0; 0x1FE0],
@@ -531,8 +658,7 @@ impl Mapper for CpuMap {
         match addr {
             0x0000..=0x07FF => self.wram[addr as usize],
             0x0800..=0x1FFF => self.wram_mirror[(addr - 0x0800) as usize],
-            0x2000..=0x2007 => self.ppu_register.addr(addr),
-            0x2008..=0x3FFF => self.ppu_register_mirror[(addr - 0x2008) as usize],
+            0x2000..=0x3FFF => self.ppu_register.addr(0x2000 + (addr & 0x7)),
             0x4000..=0x4017 => self.rp2a03.addr(addr),
             0x4018..=0x401F => self.func_apu_io[(addr - 0x4018) as usize],
             0x4020..=0x5FFF => self.erom[(addr - 0x4020) as usize],
@@ -546,8 +672,7 @@ impl Mapper for CpuMap {
         match addr {
             0x0000..=0x07FF => self.wram[addr as usize] = data,
             0x0800..=0x1FFF => self.wram_mirror[(addr - 0x0800) as usize] = data,
-            0x2000..=0x2007 => self.ppu_register.set(addr, data),
-            0x2008..=0x3FFF => self.ppu_register_mirror[(addr - 0x2008) as usize] = data,
+            0x2000..=0x3FFF => self.ppu_register.set(0x2000 + (addr & 0x7), data),
             0x4000..=0x4015 => self.rp2a03.set(addr, data),
             0x4016..=0x4017 => unreachable!(),
             0x4018..=0x401F => self.func_apu_io[(addr - 0x4017) as usize] = data,
@@ -558,3 +683,93 @@ impl Mapper for CpuMap {
         };
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::bus::cpu_map::*;
+
+    fn internal_registers_with_v(v: u16) -> InternalRegisters {
+        InternalRegisters {
+            current_vram: v,
+            ..InternalRegisters::default()
+        }
+    }
+
+    fn coarse_y(v: u16) -> u16 {
+        (v >> 5) & 0x1F
+    }
+
+    #[test]
+    fn inc_y_steps_fine_y_before_coarse_y() {
+        let mut internal_registers = internal_registers_with_v(3 << 5);
+        internal_registers.inc_y();
+        assert_eq!(internal_registers.current_vram, 0x1000 | 3 << 5);
+
+        let mut internal_registers = internal_registers_with_v(0x7000 | 3 << 5);
+        internal_registers.inc_y();
+        assert_eq!(internal_registers.current_vram, 4 << 5);
+    }
+
+    #[test]
+    fn inc_y_wraps_coarse_y_29_into_next_vertical_nametable() {
+        let mut internal_registers = internal_registers_with_v(0x7000 | 29 << 5);
+        internal_registers.inc_y();
+        assert_eq!(internal_registers.current_vram, 0x0800);
+    }
+
+    #[test]
+    fn inc_y_wraps_coarse_y_31_in_same_nametable() {
+        let mut internal_registers = internal_registers_with_v(0x7000 | 0x0800 | 31 << 5);
+        internal_registers.inc_y();
+        assert_eq!(internal_registers.current_vram, 0x0800);
+    }
+
+    #[test]
+    fn negative_scroll_reads_attribute_rows_then_wraps_in_same_nametable() {
+        let mut ppu_register = PpuRegister::new();
+        ppu_register.set(0x2005, 0);
+        ppu_register.set(0x2005, 240);
+        ppu_register.internal_registers.copy_vertical_bits();
+        assert_eq!(coarse_y(ppu_register.internal_registers.current_vram), 30);
+
+        for _ in 0..8 {
+            ppu_register.internal_registers.inc_y();
+        }
+        assert_eq!(coarse_y(ppu_register.internal_registers.current_vram), 31);
+
+        for _ in 0..8 {
+            ppu_register.internal_registers.inc_y();
+        }
+        assert_eq!(ppu_register.internal_registers.current_vram, 0);
+    }
+
+    #[test]
+    fn inc_coarse_x_wraps_into_next_horizontal_nametable() {
+        let mut internal_registers = internal_registers_with_v(0x0400 | 31);
+        internal_registers.inc_coarse_x();
+        assert_eq!(internal_registers.current_vram, 0);
+    }
+
+    #[test]
+    fn attr_addr_and_shift_pick_quadrant_of_attribute_byte() {
+        // Nametable 1, coarse X 5 and coarse Y 6.
+        let internal_registers = internal_registers_with_v(0x0400 | 6 << 5 | 5);
+        assert_eq!(internal_registers.tile_addr(), 0x24C5);
+        assert_eq!(internal_registers.attr_addr(), 0x27C9);
+        assert_eq!(internal_registers.attr_shift(), 4);
+
+        let internal_registers = internal_registers_with_v(0x0C00 | 31 << 5 | 31);
+        assert_eq!(internal_registers.attr_addr(), 0x2FFF);
+        assert_eq!(internal_registers.attr_shift(), 6);
+    }
+
+    #[test]
+    fn copy_bits_take_scroll_from_t() {
+        let mut internal_registers = internal_registers_with_v(0);
+        internal_registers.temporary_vram = 0x7FFF;
+        internal_registers.copy_horizontal_bits();
+        assert_eq!(internal_registers.current_vram, 0x041F);
+        internal_registers.copy_vertical_bits();
+        assert_eq!(internal_registers.current_vram, 0x7FFF);
+    }
+}
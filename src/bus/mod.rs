@@ -2,6 +2,8 @@ pub mod cpu_map;
 
 use crate::apu::*;
 use crate::nes::*;
+use crate::ppu::event_log::PpuEventKind;
+use crate::ppu::snapshot::PpuSnapshot;
 use crate::ppu::*;
 use cpu_map::*;
 use serde::{Deserialize, Serialize};
@@ -19,6 +21,9 @@ pub struct Bus {
     pub controller_polling_data: u16,
     controller_0_polled_data: u8,
     controller_1_polled_data: u8,
+    // CPU cycles into the current instruction at which its memory access happens.
+    #[serde(skip)]
+    pub access_cycle: u16,
 }
 
 impl Bus {
@@ -26,32 +31,144 @@ impl Bus {
         Self {
             cpu_bus: CpuMap::default(),
             ppu: PPU::new(nes),
-            apu: APU::default(),
+            apu: APU::new(nes.region),
             controller_polling_data: 0,
             controller_0_polled_data: 0,
             controller_1_polled_data: 0,
+            access_cycle: 0,
         }
     }
-}
 
-impl Mapper for Bus {
-    fn addr(&mut self, addr: u16) -> u8 {
-        match addr {
-            0x0000..=0x2001 | 0x2003..=0x2006 | 0x2008..=0x3FFF | 0x4014 | 0x4018..=0xFFFF => {
-                self.cpu_bus.addr(addr)
+    pub fn ppu_snapshot(&self) -> PpuSnapshot {
+        PpuSnapshot::new(&self.ppu, &self.cpu_bus.ppu_register)
+    }
+
+    // Returns the cycles the CPU stalls for a DMC sample fetch.
+    pub fn clock_apu(&mut self) -> u8 {
+        let stall = match self.apu.dmc.dma_addr() {
+            Some(addr) => {
+                let data = self.addr(addr);
+                self.apu.dmc.load_sample(data);
+                4
             }
-            0x2002 => {
-                self.cpu_bus.ppu_register.internal_registers.off_latch();
-                self.cpu_bus.addr(addr)
+            None => 0,
+        };
+        self.apu.dmc.clock();
+        self.apu.clock();
+        stall
+    }
+
+    fn is_rendering(&self) -> bool {
+        self.cpu_bus.ppu_register.ppu_mask.is_rendering_enabled() && self.ppu.is_rendering_line()
+    }
+
+    fn inc_vram_on_access(&mut self) {
+        if self.is_rendering() {
+            self.cpu_bus.ppu_register.rendering_inc_vram();
+        } else {
+            self.cpu_bus.ppu_register.constant_inc_vram();
+        }
+    }
+
+    fn ppu_status_addr(&mut self) -> u8 {
+        // Reading one dot before vblank starts hides the flag for the whole frame, and
+        // reading on the starting dots returns it but still cancels the NMI. A read that
+        // lands past the start before the PPU has got there sees the flag and clears it.
+        let cycles = self.access_cycle;
+        if self.ppu.is_just_before_vblank(cycles) {
+            self.ppu.suppress_vblank = true;
+            self.ppu.suppress_nmi = true;
+        } else if self.ppu.is_vblank_ahead(cycles) {
+            self.cpu_bus.ppu_register.ppu_status.in_vlank = true;
+            self.ppu.suppress_vblank = true;
+            self.ppu.suppress_nmi = self.ppu.is_just_after_vblank(cycles);
+        } else if self.ppu.is_just_after_vblank(cycles) {
+            self.ppu.nmi_pending = false;
+        }
+        let data = self.cpu_bus.addr(0x2002);
+        self.cpu_bus.ppu_register.ppu_status.in_vlank = false;
+        self.cpu_bus.ppu_register.internal_registers.off_latch();
+        data
+    }
+
+    fn ppu_data_addr(&mut self) -> u8 {
+        let addr = self.cpu_bus.ppu_register.internal_registers.current_vram & 0x3FFF;
+        self.inc_vram_on_access();
+        match addr {
+            // Palette reads are immediate; the buffer picks up the nametable byte underneath.
+            0x3F00..=0x3FFF => {
+                let mut color_idx = self.ppu.map.addr(addr) as usize;
+                self.cpu_bus
+                    .ppu_register
+                    .ppu_mask
+                    .apply_gray_scale(&mut color_idx);
+                self.cpu_bus
+                    .ppu_register
+                    .ppu_buffer
+                    .set(self.ppu.map.addr(addr - 0x1000));
+                color_idx as u8
             }
-            0x2007 => {
-                let addr = self.cpu_bus.ppu_register.internal_registers.current_vram;
-                self.cpu_bus.ppu_register.constant_inc_vram();
+            _ => {
+                let data = self.cpu_bus.ppu_register.ppu_buffer.addr();
                 self.cpu_bus
                     .ppu_register
                     .ppu_buffer
                     .set(self.ppu.map.addr(addr));
-                self.cpu_bus.ppu_register.ppu_buffer.addr()
+                data
+            }
+        }
+    }
+
+    fn ppu_register_addr(&mut self, addr: u16) -> u8 {
+        let frame = self.ppu.frame;
+        let (data, driven_bits) = match addr {
+            0x2002 => {
+                let data = self.ppu_status_addr() & 0b11100000;
+                (data, 0b11100000)
+            }
+            0x2004 => (self.cpu_bus.addr(addr), 0b11111111),
+            0x2007 => {
+                let is_palette =
+                    self.cpu_bus.ppu_register.internal_registers.current_vram & 0x3FFF >= 0x3F00;
+                let data = self.ppu_data_addr();
+                if is_palette {
+                    (data & 0b00111111, 0b00111111)
+                } else {
+                    (data, 0b11111111)
+                }
+            }
+            // Write-only registers return whatever is left on the I/O latch.
+            _ => return self.cpu_bus.ppu_register.open_bus.addr(frame),
+        };
+        let open_bus = &mut self.cpu_bus.ppu_register.open_bus;
+        let data = (open_bus.addr(frame) & !driven_bits) | data;
+        open_bus.set_bits(data, driven_bits, frame);
+        data
+    }
+
+    fn ppu_register_set(&mut self, addr: u16, data: u8) {
+        self.cpu_bus.ppu_register.open_bus.set(data, self.ppu.frame);
+        match addr {
+            0x2007 => {
+                let addr = self.cpu_bus.ppu_register.internal_registers.current_vram & 0x3FFF;
+                self.inc_vram_on_access();
+                self.ppu.map.set(addr, data);
+            }
+            _ => self.cpu_bus.set(addr, data),
+        }
+    }
+}
+
+impl Mapper for Bus {
+    fn addr(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF | 0x4014 | 0x4018..=0xFFFF => self.cpu_bus.addr(addr),
+            0x2000..=0x3FFF => {
+                let addr = 0x2000 + (addr & 0x7);
+                let data = self.ppu_register_addr(addr);
+                self.ppu
+                    .log_event(PpuEventKind::Read, addr, data, self.access_cycle);
+                data
             }
             0x4000 => self.apu.pulse1.addr(0),
             0x4001 => self.apu.pulse1.addr(1),
@@ -62,7 +179,7 @@ impl Mapper for Bus {
             0x4006 => self.apu.pulse2.addr(2),
             0x4007 => self.apu.pulse2.addr(3),
             0x4008..=0x4013 => 0,
-            0x4015 => self.apu.channel_controller.addr(),
+            0x4015 => self.apu.status(),
             0x4016 => {
                 let n = self.controller_0_polled_data & 0x1;
                 self.controller_0_polled_data >>= 0x1;
@@ -78,13 +195,17 @@ impl Mapper for Bus {
 
     fn set(&mut self, addr: u16, data: u8) {
         match addr {
-            0x0000..=0x2006 | 0x2008..=0x3FFF | 0x4014 | 0x4018..=0xFFFF => {
-                self.cpu_bus.set(addr, data)
+            0x0000..=0x1FFF | 0x4018..=0x7FFF => self.cpu_bus.set(addr, data),
+            0x4014 | 0x8000..=0xFFFF => {
+                self.ppu
+                    .log_event(PpuEventKind::Write, addr, data, self.access_cycle);
+                self.cpu_bus.set(addr, data);
             }
-            0x2007 => {
-                let addr = self.cpu_bus.ppu_register.internal_registers.current_vram;
-                self.cpu_bus.ppu_register.constant_inc_vram();
-                self.ppu.map.set(addr, data);
+            0x2000..=0x3FFF => {
+                let addr = 0x2000 + (addr & 0x7);
+                self.ppu
+                    .log_event(PpuEventKind::Write, addr, data, self.access_cycle);
+                self.ppu_register_set(addr, data);
             }
             0x4000 => self.apu.pulse1.set(0, data),
             0x4001 => self.apu.pulse1.sweep_set(data),
@@ -102,8 +223,11 @@ impl Mapper for Bus {
             0x400D => (),
             0x400E => self.apu.noise.set(2, data),
             0x400F => self.apu.noise.set(3, data),
-            0x4010..=0x4013 => (),
-            0x4015 => self.apu.channel_controller.set(data),
+            0x4010 => self.apu.dmc.set(0, data),
+            0x4011 => self.apu.dmc.set(1, data),
+            0x4012 => self.apu.dmc.set(2, data),
+            0x4013 => self.apu.dmc.set(3, data),
+            0x4015 => self.apu.set_status(data),
             0x4016 => match data % 2 {
                 1 => {
                     let polling_data = self.controller_polling_data;
@@ -116,7 +240,7 @@ impl Mapper for Bus {
                 _ => (),
             },
             0x4017 => {
-                self.apu.frame_counter.set(data);
+                self.apu.set_frame_counter(data);
             }
         };
     }
@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::bus::*;
 use crate::nes::*;
+use crate::ppu::event_log::PpuEventKind;
 use crate::util::*;
 use operator::*;
 use register::*;
@@ -380,16 +381,25 @@ impl CPU {
     pub fn interrupt(&mut self, intr: Interrupt) {
         match intr {
             Interrupt::Nmi => {
+                self.bus.ppu.log_event(PpuEventKind::Nmi, 0xFFFA, 0, 0);
                 self.set_break_mode(false);
-                self.set_interrupt(true);
                 self.push_pc();
                 let p = self.get_p();
                 self.push_stack(p);
+                self.set_interrupt(true);
                 let (l_data, h_data) = self.bus.cpu_bus.lh_addr(0xFFFA);
                 self.register.set_pc(combine_high_low(l_data, h_data));
             }
             Interrupt::Reset => self.reset(),
-            Interrupt::Irq => unimplemented!(),
+            Interrupt::Irq => {
+                self.set_break_mode(false);
+                self.push_pc();
+                let p = self.get_p();
+                self.push_stack(p);
+                self.set_interrupt(true);
+                let (l_data, h_data) = self.bus.cpu_bus.lh_addr(0xFFFE);
+                self.register.set_pc(combine_high_low(l_data, h_data));
+            }
             Interrupt::Brk => unimplemented!(),
         }
     }
@@ -399,6 +409,10 @@ impl CPU {
         self.total_cycle += data as i64;
     }
 
+    pub fn stall(&mut self, cycles: u8) {
+        self.inc_cycle(cycles);
+    }
+
     #[cfg(feature = "nestest_without_gui")]
     pub fn reset(&mut self) {
         self.inc_cycle(7);
@@ -449,7 +463,7 @@ impl CPU {
         self.register.mut_access_p().set_decimal(data);
     }
 
-    fn get_interrupt(&self) -> bool {
+    pub fn get_interrupt(&self) -> bool {
         self.register.access_p().get_interrupt()
     }
 
@@ -589,11 +603,10 @@ impl CPU {
         self.bus.addr(map)
     }
 
-    fn push_oam(&mut self) {
-        let data = self.bus.addr(0x2004);
+    fn push_oam(&mut self, data: u8) {
         self.bus.ppu.oam_buf.push(data);
         if self.bus.ppu.oam_buf.len() == 4 {
-            let target = self.bus.addr(0x2003);
+            let target = self.bus.cpu_bus.ppu_register.oam_addr();
             let data = &self.bus.ppu.oam_buf;
             self.bus.ppu.primary_oam.put_sprite_info(data, target);
             self.bus.ppu.oam_buf = vec![];
@@ -618,7 +631,7 @@ impl CPU {
     fn bus_set(&mut self, addr: u16, data: u8) {
         self.bus.set(addr, data);
         match addr {
-            0x2004 => self.push_oam(),
+            0x2004 => self.push_oam(data),
             0x4014 => self.set_oam(),
             _ => (),
         }
@@ -1194,6 +1207,10 @@ impl CPU {
         }
     }
     fn rti(&mut self) {
+        let access_cycle = self.bus.access_cycle;
+        self.bus
+            .ppu
+            .log_event(PpuEventKind::Rti, 0, 0, access_cycle);
         let data = self.pull_stack();
         let l_data = self.pull_stack();
         let h_data = self.pull_stack();
@@ -1331,6 +1348,8 @@ impl CPU {
                 let addr_mode = addr_mode.clone();
                 let cycle = cycle.clone();
                 let reg_addr = self.ex_addr_mode(&addr_mode);
+                // Loads and stores touch the bus on the last cycle of the instruction.
+                self.bus.access_cycle = self.cycle + cycle as u16 - 1;
                 self.run_ope(reg_addr, ope_kind.clone(), addr_mode);
                 self.inc_cycle(cycle);
                 if cfg!(feature = "with_dump") {
@@ -1407,7 +1426,8 @@ mod test {
             let mut buffer = Vec::new();
             f.read_to_end(&mut buffer).unwrap();
             let header = Header::new(&buffer);
-            Self { header }
+            let region = header.region();
+            Self { header, region }
         }
     }
 
@@ -1444,7 +1464,7 @@ mod test {
         fn fetch_next_lh_register(&mut self) -> (u8, u8) {
             self.inc_pc(1);
             let (l_data, h_data) = self.fetch_lh_register();
-            self.dec_pc(1);
+            self.register.dec_pc(1);
             (l_data, h_data)
         }
     }
@@ -1654,4 +1674,21 @@ mod test {
 
         assert_eq!(addr, data);
     }
+
+    #[test]
+    fn ppu_write_is_logged_at_access_dot() {
+        let mut cpu = prepare_cpu_for_addr_mode_test(AddrMode::Abs);
+        cpu.bus.cpu_bus.prg_rom1[0..3].copy_from_slice(&[0x8D, 0x01, 0x20]);
+        cpu.clear_cycle();
+        cpu.bus.ppu.drawing_line = 20;
+        cpu.bus.ppu.cycle = 100;
+
+        cpu.ex_ope();
+
+        let event = *cpu.bus.ppu.event_log.current_events().last().unwrap();
+        assert_eq!(
+            (event.addr, event.scanline, event.dot),
+            (0x2001, 20, 100 + 3 * 3)
+        );
+    }
 }
@@ -2,9 +2,10 @@ pub const TILE_COUNTS_ON_WIDTH: usize = 32;
 pub const WINDOW_WIDTH: u32 = 512;
 pub const WINDOW_HEIGHT: u32 = 480;
 pub const PPU_DRAW_LINE_CYCLE: u16 = 341;
-pub const VBLANK_LINE: u16 = 241;
-const VBLANK_LINES: u16 = 20;
+pub const VBLANK_START_CYCLE: u16 = 1;
 pub const VISIBLE_LINES: u16 = 240;
-pub const TOTAL_LINE: u16 = VBLANK_LINES + VISIBLE_LINES + 2;
-pub const APU_UPDATE_CYCLE: u16 = 2;
-pub const APU_TRIANGLE_CYCLE: u16 = 1;
+pub const POST_RENDER_LINE: u16 = VISIBLE_LINES;
+pub const AUDIO_CHUNK_SAMPLES: usize = 256;
+pub const AUDIO_TARGET_SAMPLES: usize = 2048;
+pub const AUDIO_MAX_RATE_DELTA: f64 = 0.005;
+pub const CHANNEL_GAIN_STEPS: [f32; 4] = [1.0, 0.5, 0.25, 2.0];
@@ -1,8 +1,11 @@
 pub mod configure;
+pub mod ntsc;
+pub mod palette;
+pub mod recorder;
 
-use crate::apu::noise::Noise;
-use crate::apu::pulse::Pulse;
-use crate::apu::triangle::Triangle;
+use crate::apu::audio_sink::AudioSink;
+use crate::apu::mixer::{Channel, ChannelSettings};
+use crate::apu::SAMPLE_RATE;
 use crate::emulator::configure::*;
 use sdl2::audio::AudioDevice;
 use sdl2::audio::AudioSpecDesired;
@@ -12,18 +15,23 @@ use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
 use std::path::Path;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 pub mod texture;
+pub mod viewer;
 use crate::bus::cpu_map::*;
 use crate::bus::Mapper;
 use crate::cpu::*;
+use crate::emulator::ntsc::{NtscFilter, NtscSettings, NTSC_WIDTH};
+use crate::emulator::palette::Palettes;
+use crate::emulator::recorder::Recorder;
 use crate::emulator::texture::TextureBuffer;
+use crate::emulator::viewer::Viewer;
 use crate::nes::*;
 use crate::ppu::oam::SpriteInfo;
-use crate::util::*;
-use rustc_hash::*;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use crate::ppu::snapshot::PpuSnapshot;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture, TextureCreator};
@@ -33,18 +41,25 @@ use sdl2::Sdl;
 
 pub struct Emulator {
     pub cpu: CPU,
-    ppu_cycle: u16,
-    apu_triangle_cycle: u16,
-    apu_pulse_cycle: u16,
-    drawing_line: u16,
+    audio_samples: Vec<f32>,
+    ppu_dot_remainder: u16,
+    background_opaque: [bool; 256],
+    line_buffer: [u16; 256],
+    sprite_zero_hit_dot: Option<u16>,
+    sprite_limit: Option<usize>,
     sdl: Sdl,
     canvas: Canvas<Window>,
     texture_buffer: TextureBuffer,
+    palettes: Palettes,
+    ntsc_filter: NtscFilter,
+    is_ntsc_filter_enabled: bool,
+    viewer: Viewer,
+    debug_canvas: Option<Canvas<Window>>,
     pad_data: u16,
-    audio_device_pulse1: AudioDevice<Pulse>,
-    audio_device_pulse2: AudioDevice<Pulse>,
-    audio_device_triangle: AudioDevice<Triangle>,
-    audio_device_noise: AudioDevice<Noise>,
+    audio_device: AudioDevice<AudioSink>,
+    recorder: Option<Recorder>,
+    is_record_toggle_pending: bool,
+    is_record_channels: bool,
 }
 
 impl Emulator {
@@ -65,133 +80,115 @@ impl Emulator {
         let canvas = window
             .into_canvas()
             .target_texture()
-            .present_vsync()
             .build()
             .map_err(|e| e.to_string())
             .unwrap();
 
-        let texture_buffer = TextureBuffer::default();
+        let args: Vec<String> = env::args().collect();
+        let palettes = Palettes::from_args(&args).unwrap();
+        let mut texture_buffer = TextureBuffer::default();
+        texture_buffer.set_palette(palettes.current());
+        let ntsc_filter = NtscFilter::new(NtscSettings::from_args(&args));
+        let is_ntsc_filter_enabled = args.iter().any(|arg| arg == "filter=ntsc");
+        let sprite_limit = if args.iter().any(|arg| arg == "no_sprite_limit") {
+            None
+        } else {
+            Some(8)
+        };
+        let debug_canvas = if args.iter().any(|arg| arg == "debug") {
+            Some(Self::open_debug_canvas(&sdl_context).unwrap())
+        } else {
+            None
+        };
 
         let audio_subsystem = sdl_context.audio().unwrap();
         let desired_spec = AudioSpecDesired {
-            freq: None,
-            channels: None,
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
             samples: None,
         };
-        let default_pulse_audio = |desired_spec: &mut AudioSpecDesired| {
-            audio_subsystem.open_playback(None, desired_spec, |_spec| Pulse::default())
-        };
-        let audio_device_pulse1 = default_pulse_audio(&mut desired_spec.clone()).unwrap();
-        let audio_device_pulse2 = default_pulse_audio(&mut desired_spec.clone()).unwrap();
-
-        let default_triangle_audio = |desired_spec: &mut AudioSpecDesired| {
-            audio_subsystem.open_playback(None, desired_spec, |_spec| Triangle::default())
-        };
-        let audio_device_triangle = default_triangle_audio(&mut desired_spec.clone()).unwrap();
-
-        let default_noise_audio = |desired_spec: &mut AudioSpecDesired| {
-            audio_subsystem.open_playback(None, desired_spec, |_spec| Noise::default())
-        };
-        let audio_device_noise = default_noise_audio(&mut desired_spec.clone()).unwrap();
-
-        audio_device_pulse1.resume();
-        audio_device_pulse2.resume();
-        audio_device_triangle.resume();
-        audio_device_noise.resume();
+        let audio_device = audio_subsystem
+            .open_playback(None, &desired_spec, |_spec| AudioSink::default())
+            .unwrap();
+        audio_device.resume();
+
+        let is_record_channels = args.iter().any(|arg| arg == "record_channels");
+        let recorder = args
+            .iter()
+            .find_map(|arg| arg.strip_prefix("record="))
+            .map(|path| Recorder::create(Path::new(path), is_record_channels).unwrap());
+        if recorder.is_some() {
+            cpu.bus.apu.start_record_tracks(is_record_channels);
+        }
 
         Self {
             cpu,
-            ppu_cycle: 0,
-            apu_triangle_cycle: 0,
-            apu_pulse_cycle: 0,
-            drawing_line: 0,
+            audio_samples: Vec::with_capacity(AUDIO_CHUNK_SAMPLES),
+            ppu_dot_remainder: 0,
+            background_opaque: [false; 256],
+            line_buffer: [0; 256],
+            sprite_zero_hit_dot: None,
+            sprite_limit,
             sdl: sdl_context,
             canvas,
             texture_buffer,
+            palettes,
+            ntsc_filter,
+            is_ntsc_filter_enabled,
+            viewer: Viewer::default(),
+            debug_canvas,
             pad_data: 0,
-            audio_device_pulse1,
-            audio_device_pulse2,
-            audio_device_triangle,
-            audio_device_noise,
+            audio_device,
+            recorder,
+            is_record_toggle_pending: false,
+            is_record_channels,
         }
     }
 
-    pub fn startup(&mut self) {
-        self.cpu.set_pc(0xFFFC);
-        self.cpu.dec_p(3);
-        self.cpu.reset();
+    pub fn ppu_snapshot(&self) -> PpuSnapshot {
+        self.cpu.bus.ppu_snapshot()
     }
 
-    fn inc_apu_triangle_cycle(&mut self) {
-        self.apu_triangle_cycle += self.cpu.cycle as u16;
+    pub fn channel_settings_mut(&mut self) -> &mut ChannelSettings {
+        &mut self.cpu.bus.apu.mixer.channel_settings
     }
 
-    fn inc_apu_pulse_cycle(&mut self) {
-        self.apu_pulse_cycle += self.cpu.cycle as u16;
-    }
-
-    fn inc_ppu_cycle(&mut self) {
-        self.ppu_cycle += (self.cpu.cycle * 3) as u16;
+    pub fn startup(&mut self) {
+        self.cpu.set_pc(0xFFFC);
+        self.cpu.dec_p(3);
+        self.cpu.reset();
     }
 
-    fn update_texture_buffer(&mut self, texture: &mut Texture) -> Result<(), String> {
-        texture.with_lock(None, |buffer: &mut [u8], _pitch: usize| {
-            for (key, value) in self.texture_buffer.buffer.iter().enumerate() {
-                buffer[key] = *value;
+    fn copy_to_texture(texture: &mut Texture, rgb: &[u8], width: usize) -> Result<(), String> {
+        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (y, line) in rgb.chunks(width * 3).enumerate() {
+                buffer[y * pitch..y * pitch + width * 3].copy_from_slice(line);
             }
         })?;
 
         Ok(())
     }
 
-    pub fn render_all_sprites(&mut self, sprites_num: u32) -> Result<(), String> {
-        let mut event_pump = self.sdl.event_pump()?;
-        let texture_creator: TextureCreator<_> = self.canvas.texture_creator();
-        let mut texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB24, 256, 256)
-            .map_err(|e| e.to_string())?;
-        let ppu_map = &mut self.cpu.bus.ppu.map;
-
-        for n in 0..sprites_num {
-            for i in 0..8 {
-                let sprite_row_line = ppu_map.addr((n * 0x10) as u16 + i);
-                let sprite_high_line = ppu_map.addr((n * 0x10) as u16 + i + 0x8);
-                for j in 0..8 {
-                    let (idx, x, y) = {
-                        let idx = {
-                            let r = ((sprite_row_line & (0b1 << (7 - j))) != 0) as u16;
-                            let h = ((sprite_high_line & (0b1 << (7 - j))) != 0) as u16;
-                            (h << 1 | r) as usize
-                        };
-                        let x = j as u32 + (n % TILE_COUNTS_ON_WIDTH as u32) * 8;
-                        let y = i as u32 + (n / TILE_COUNTS_ON_WIDTH as u32) * 8;
-                        (idx, x, y)
-                    };
-                    self.texture_buffer.insert_color(x as u8, y as u8, idx);
-                }
-            }
+    fn frame_width(&self) -> usize {
+        if self.is_ntsc_filter_enabled {
+            NTSC_WIDTH
+        } else {
+            TILE_COUNTS_ON_WIDTH * 8
         }
+    }
 
-        self.update_texture_buffer(&mut texture)?;
-
-        self.canvas.clear();
-        self.canvas
-            .copy(&texture, None, Rect::new(0, 0, WINDOW_WIDTH, WINDOW_WIDTH))?;
-        self.canvas.present();
-
-        'show_sprites: loop {
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
-                        ..
-                    } => break 'show_sprites,
-                    _ => {}
-                }
-            }
+    fn update_texture_buffer(&mut self, texture: &mut Texture) -> Result<(), String> {
+        let width = self.frame_width();
+        if self.is_ntsc_filter_enabled {
+            let frame = self.cpu.bus.ppu.frame;
+            let rgb = self
+                .ntsc_filter
+                .apply(&self.cpu.bus.ppu.frame_buffer.pixels, frame);
+            Self::copy_to_texture(texture, rgb, width)
+        } else {
+            self.texture_buffer.convert(&self.cpu.bus.ppu.frame_buffer);
+            Self::copy_to_texture(texture, &self.texture_buffer.buffer, width)
         }
-        Ok(())
     }
 
     fn reference_keycode(&mut self, keycode: Keycode) -> u16 {
@@ -216,10 +213,9 @@ impl Emulator {
         }
     }
 
-    fn save_state(&self) {
+    fn rom_name() -> String {
         let file_path: Vec<String> = env::args().collect();
-
-        let file_name = Path::new(&file_path[file_path.len() - 1])
+        Path::new(&file_path[file_path.len() - 1])
             .to_str()
             .unwrap()
             .split('/')
@@ -227,36 +223,186 @@ impl Emulator {
             .unwrap()
             .split('.')
             .nth(0)
-            .unwrap();
+            .unwrap()
+            .to_string()
+    }
+
+    fn save_state(&self) {
+        let file_name = Self::rom_name();
         let mut file = File::create(format!("saves/{}_save.json", file_name)).unwrap();
         let serialized = serde_json::to_string(&self.cpu).unwrap();
         file.write_fmt(format_args!("{}", serialized)).unwrap();
     }
 
     fn load_state(&mut self) {
-        let file_path: Vec<String> = env::args().collect();
-        let file_name = Path::new(&file_path[file_path.len() - 1])
-            .to_str()
-            .unwrap()
-            .split('/')
-            .last()
-            .unwrap()
-            .split('.')
-            .nth(0)
-            .unwrap();
+        let file_name = Self::rom_name();
 
         match File::open(format!("saves/{}_save.json", file_name)) {
             Ok(file) => {
                 let mut buf_reader = BufReader::new(file);
                 let mut contents = String::new();
                 buf_reader.read_to_string(&mut contents).unwrap();
-                let cpu: CPU = serde_json::from_str(&contents).unwrap();
+                let mut cpu: CPU = serde_json::from_str(&contents).unwrap();
+                // Channel settings and record tracks aren't part of the state.
+                cpu.bus.apu.mixer.channel_settings =
+                    self.cpu.bus.apu.mixer.channel_settings.clone();
                 self.cpu = cpu;
+                if let Some(recorder) = self.recorder.as_ref() {
+                    let is_record_channels = recorder.is_record_channels();
+                    self.cpu.bus.apu.start_record_tracks(is_record_channels);
+                }
             }
             _ => (),
         }
     }
 
+    fn open_debug_canvas(sdl: &Sdl) -> Result<Canvas<Window>, String> {
+        let window = sdl
+            .video()?
+            .window("fc2 - debug", 768 + PPU_DRAW_LINE_CYCLE as u32, 480)
+            .build()
+            .map_err(|e| e.to_string())?;
+        window.into_canvas().build().map_err(|e| e.to_string())
+    }
+
+    fn toggle_debug_window(&mut self) -> Result<(), String> {
+        self.debug_canvas = match self.debug_canvas {
+            Some(_) => None,
+            None => Some(Self::open_debug_canvas(&self.sdl)?),
+        };
+        Ok(())
+    }
+
+    fn update_debug_window(&mut self) -> Result<(), String> {
+        let canvas = match self.debug_canvas.as_mut() {
+            Some(canvas) => canvas,
+            None => return Ok(()),
+        };
+        let colors = &self.palettes.current().colors;
+        let image = self.viewer.compose(&self.cpu.bus.ppu_snapshot(), colors);
+        let texture_creator = canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_static(
+                PixelFormatEnum::RGB24,
+                image.width as u32,
+                image.height as u32,
+            )
+            .map_err(|e| e.to_string())?;
+        texture
+            .update(None, &image.buffer, image.width * 3)
+            .map_err(|e| e.to_string())?;
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        Ok(())
+    }
+
+    fn dump_debug_images(&mut self) {
+        let snapshot = self.ppu_snapshot();
+        let colors = &self.palettes.current().colors;
+        if let Err(e) = self.viewer.dump(&snapshot, colors, Path::new("debug")) {
+            eprintln!("Failed to dump debug images: {}", e);
+        }
+    }
+
+    fn toggle_sprite_limit(&mut self) {
+        self.sprite_limit = match self.sprite_limit {
+            Some(_) => None,
+            None => Some(8),
+        };
+    }
+
+    fn switch_palette(&mut self) {
+        let palette = self.palettes.select_next();
+        self.texture_buffer.set_palette(palette);
+        let title = format!("fc2 - {}", palette.name);
+        self.canvas.window_mut().set_title(&title).unwrap();
+    }
+
+    fn request_recording_toggle(&mut self) {
+        self.is_record_toggle_pending = true;
+    }
+
+    // The ended frame's samples go to the old recording before switching.
+    fn toggle_recording(&mut self) {
+        self.is_record_toggle_pending = false;
+        self.update_audio_samples();
+        match self.recorder.take() {
+            Some(recorder) => {
+                self.cpu.bus.apu.stop_record_tracks();
+                if let Err(e) = Self::finish_recording(recorder) {
+                    eprintln!("Failed to finish recording: {}", e);
+                }
+            }
+            None => {
+                let path = format!(
+                    "recordings/{}_{}.wav",
+                    Self::rom_name(),
+                    self.cpu.bus.ppu.frame
+                );
+                match Recorder::create(Path::new(&path), self.is_record_channels) {
+                    Ok(recorder) => {
+                        let is_record_channels = recorder.is_record_channels();
+                        self.cpu.bus.apu.start_record_tracks(is_record_channels);
+                        self.recorder = Some(recorder);
+                    }
+                    Err(e) => eprintln!("Failed to start recording: {}", e),
+                }
+            }
+        }
+    }
+
+    fn finish_recording(recorder: Recorder) -> Result<(), String> {
+        let path = recorder.path().to_path_buf();
+        let (frames, samples) = recorder.finish()?;
+        println!(
+            "Recorded {} frames, {} samples to {}",
+            frames,
+            samples,
+            path.display()
+        );
+        Ok(())
+    }
+
+    fn channel_for_key(key: Keycode) -> Option<Channel> {
+        match key {
+            Keycode::Num1 => Some(Channel::Pulse1),
+            Keycode::Num2 => Some(Channel::Pulse2),
+            Keycode::Num3 => Some(Channel::Triangle),
+            Keycode::Num4 => Some(Channel::Noise),
+            Keycode::Num5 => Some(Channel::Dmc),
+            _ => None,
+        }
+    }
+
+    fn control_channel(&mut self, channel: Channel, keymod: Mod) {
+        let settings = self.channel_settings_mut();
+        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+            settings.set_solo(channel, !settings.is_soloed(channel));
+        } else if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+            let step = CHANNEL_GAIN_STEPS
+                .iter()
+                .position(|gain| *gain == settings.gain(channel))
+                .map_or(0, |n| (n + 1) % CHANNEL_GAIN_STEPS.len());
+            settings.set_gain(channel, CHANNEL_GAIN_STEPS[step]);
+        } else {
+            settings.set_mute(channel, !settings.is_muted(channel));
+        }
+        self.show_channel_settings();
+    }
+
+    fn show_channel_settings(&mut self) {
+        let settings = &self.cpu.bus.apu.mixer.channel_settings;
+        let channels: Vec<String> = Channel::ALL
+            .iter()
+            .map(|channel| match settings.is_audible(*channel) {
+                true => format!("{}x{}", channel.name(), settings.gain(*channel)),
+                false => format!("({})", channel.name()),
+            })
+            .collect();
+        let title = format!("fc2 - {}", channels.join(" "));
+        self.canvas.window_mut().set_title(&title).unwrap();
+    }
+
     fn handle_keyboard(&mut self, event_pump: &mut EventPump) -> Option<()> {
         for event in event_pump.poll_iter() {
             match event {
@@ -273,6 +419,55 @@ impl Emulator {
                     keycode: Some(Keycode::F2),
                     ..
                 } => self.load_state(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => self.switch_palette(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => self.is_ntsc_filter_enabled = !self.is_ntsc_filter_enabled,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => self.dump_debug_images(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => self.viewer.select_next_pattern_palette(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => {
+                    if let Err(e) = self.toggle_debug_window() {
+                        eprintln!("Failed to open debug window: {}", e);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => self.toggle_sprite_limit(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => self.request_recording_toggle(),
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } if Self::channel_for_key(key).is_some() => {
+                    self.control_channel(Self::channel_for_key(key)?, keymod)
+                }
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if window_id == self.canvas.window().id() {
+                        return None;
+                    }
+                    self.debug_canvas = None;
+                }
                 Event::KeyDown {
                     keycode: Some(key), ..
                 } => {
@@ -295,7 +490,7 @@ impl Emulator {
         let mut event_pump = self.sdl.event_pump()?;
         let texture_creator: TextureCreator<_> = self.canvas.texture_creator();
         let mut texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB24, 256, 256)
+            .create_texture_streaming(PixelFormatEnum::RGB24, NTSC_WIDTH as u32, 256)
             .map_err(|e| e.to_string())?;
         'running: loop {
             match self.handle_keyboard(&mut event_pump) {
@@ -304,41 +499,18 @@ impl Emulator {
             }
             self.run(&mut texture)?;
         }
+        if let Some(recorder) = self.recorder.take() {
+            Self::finish_recording(recorder)?;
+        }
 
         Ok(())
     }
 
-    fn enable_render_bottom(&self) -> bool {
-        self.drawing_line >= 8
-    }
-
-    fn insert_sprite_behind_background_for_big_size(&mut self) -> Result<(), String> {
-        self.insert_sprites_for_big_top()?;
-        if self.enable_render_bottom() {
-            self.set_secondary_oam_behind_background_on_bottom();
-            self.insert_sprites_for_big_bottom()?;
-        };
-        Ok(())
-    }
-
-    fn insert_sprite_front_of_background_for_big_size(&mut self) -> Result<(), String> {
-        self.insert_sprites_for_big_top()?;
-        if self.enable_render_bottom() {
-            self.set_secondary_oam_front_of_background_on_bottom();
-            self.insert_sprites_for_big_bottom()?;
-        };
-        Ok(())
-    }
-
     fn insert_sprites_behinds_background(&mut self) -> Result<(), String> {
         if self.cpu.bus.cpu_bus.ppu_register.ppu_mask.is_show_sprites() {
             if self.cpu.bus.cpu_bus.ppu_register.ppu_mask.show_sprites {
-                self.set_secondary_oam_behind_background_for_nomal();
-                if self.cpu.bus.cpu_bus.ppu_register.ppu_ctrl.for_big() {
-                    self.insert_sprite_behind_background_for_big_size()?;
-                } else {
-                    self.insert_normal_size_sprites()?;
-                }
+                self.set_secondary_oam_behind_background();
+                self.insert_sprites()?;
             }
         }
         Ok(())
@@ -347,12 +519,8 @@ impl Emulator {
     fn insert_sprites_front_of_background(&mut self) -> Result<(), String> {
         if self.cpu.bus.cpu_bus.ppu_register.ppu_mask.is_show_sprites() {
             if self.cpu.bus.cpu_bus.ppu_register.ppu_mask.show_sprites {
-                self.set_secondary_oam_front_of_background_for_nomal();
-                if self.cpu.bus.cpu_bus.ppu_register.ppu_ctrl.for_big() {
-                    self.insert_sprite_front_of_background_for_big_size()?;
-                } else {
-                    self.insert_normal_size_sprites()?;
-                }
+                self.set_secondary_oam_front_of_background();
+                self.insert_sprites()?;
             }
         }
         Ok(())
@@ -360,354 +528,264 @@ impl Emulator {
 
     fn draw_line(&mut self, texture: &mut Texture) -> Result<(), String> {
         self.update_texture_buffer(texture)?;
+        let src = Rect::new(0, 0, self.frame_width() as u32, 256);
         self.canvas
-            .copy(&texture, None, Rect::new(0, 0, WINDOW_WIDTH, WINDOW_WIDTH))?;
+            .copy(&texture, src, Rect::new(0, 0, WINDOW_WIDTH, WINDOW_WIDTH))?;
         self.canvas.present();
+        self.update_debug_window()?;
 
         Ok(())
     }
 
     fn cpu_update(&mut self) {
-        let now = Instant::now();
         self.cpu.ex_ope();
-        while now.elapsed().as_nanos() < 555 {}
     }
 
-    fn update_triangle_audio_devices(&mut self) {
-        self.cpu.bus.apu.triangle.update(
-            &mut self.cpu.bus.apu.frame_counter,
-            &mut self.cpu.bus.apu.channel_controller.enable_triangle,
-            &mut self.audio_device_triangle.lock(),
-        );
+    // DMC fetches stall the CPU while the APU keeps running.
+    fn clock_apu(&mut self) {
+        let mut cycles = self.cpu.cycle;
+        let mut stall = 0;
+        while cycles > 0 {
+            cycles -= 1;
+            let dmc_stall = self.cpu.bus.clock_apu();
+            cycles += dmc_stall as u16;
+            stall += dmc_stall;
+        }
+        self.cpu.stall(stall);
     }
 
-    fn update_pulse_audio_devices(&mut self) {
-        self.cpu.bus.apu.pulse1.update(
-            &mut self.cpu.bus.apu.frame_counter,
-            &mut self.cpu.bus.apu.channel_controller.enable_pulse1,
-            &mut self.audio_device_pulse1.lock(),
-        );
+    fn update_audio_samples(&mut self) {
+        self.cpu.bus.apu.read_samples(&mut self.audio_samples);
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.write(&mut self.cpu.bus.apu) {
+                eprintln!("Failed to write recording: {}", e);
+                self.recorder = None;
+                self.cpu.bus.apu.stop_record_tracks();
+            }
+        }
+        if self.audio_samples.len() >= AUDIO_CHUNK_SAMPLES {
+            self.audio_device.lock().push(&self.audio_samples);
+            self.audio_samples.clear();
+        }
+    }
 
-        self.cpu.bus.apu.pulse2.update(
-            &mut self.cpu.bus.apu.frame_counter,
-            &mut self.cpu.bus.apu.channel_controller.enable_pulse2,
-            &mut self.audio_device_pulse2.lock(),
-        );
+    // Waits for the audio device to drain to the target fill and nudges the resampling
+    // ratio by what is left, so the fill settles instead of drifting.
+    fn sync_audio(&mut self) {
+        let region = self.cpu.bus.ppu.region;
+        let max_wait = Duration::from_secs_f32(2.0 / region.frame_rate());
+        let now = Instant::now();
+        while self.buffered_audio_samples() > AUDIO_TARGET_SAMPLES && now.elapsed() < max_wait {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let error = AUDIO_TARGET_SAMPLES as f64 - self.buffered_audio_samples() as f64;
+        let ratio =
+            1.0 + (error / AUDIO_TARGET_SAMPLES as f64).clamp(-1.0, 1.0) * AUDIO_MAX_RATE_DELTA;
+        self.cpu.bus.apu.set_sample_rate_ratio(ratio);
     }
 
-    fn update_noise_audio_devices(&mut self) {
-        self.cpu.bus.apu.noise.update(
-            &mut self.cpu.bus.apu.frame_counter,
-            &mut self.cpu.bus.apu.channel_controller.enable_noise,
-            &mut self.audio_device_noise.lock(),
-        );
+    fn buffered_audio_samples(&mut self) -> usize {
+        self.audio_device.lock().buffered_samples() + self.audio_samples.len()
     }
 
     fn apu_update(&mut self) {
-        self.inc_apu_triangle_cycle();
-        self.inc_apu_pulse_cycle();
+        self.clock_apu();
+        self.update_audio_samples();
+    }
 
-        while self.apu_triangle_cycle >= APU_TRIANGLE_CYCLE {
-            self.apu_triangle_cycle -= APU_TRIANGLE_CYCLE;
-            self.update_triangle_audio_devices();
-        }
-        while self.apu_pulse_cycle >= APU_UPDATE_CYCLE {
-            self.apu_pulse_cycle -= APU_UPDATE_CYCLE;
-            self.update_pulse_audio_devices();
-            self.update_noise_audio_devices();
-        }
+    fn render_line(&mut self) -> Result<(), String> {
+        let y = self.cpu.bus.ppu.drawing_line as u8;
+        self.line_buffer
+            .copy_from_slice(self.cpu.bus.ppu.frame_buffer.line(y));
+        self.background_opaque = [false; 256];
+        self.insert_universal_background();
+        self.insert_sprites_behinds_background()?;
+        self.insert_front_background();
+        self.insert_sprites_front_of_background()?;
+        self.sprite_zero_hit_dot = self.find_sprite_zero_hit_dot();
+        self.cpu.bus.ppu.frame_buffer.set_line(y, &self.line_buffer);
+        Ok(())
     }
 
     fn ppu_update(&mut self, texture: &mut Texture) -> Result<(), String> {
-        self.inc_ppu_cycle();
-        if self.ppu_cycle >= PPU_DRAW_LINE_CYCLE {
-            self.ppu_cycle -= PPU_DRAW_LINE_CYCLE;
-            if self.drawing_line < VISIBLE_LINES {
-                self.insert_universal_background();
-                self.insert_sprites_behinds_background()?;
-                self.insert_front_background();
-                self.insert_sprites_front_of_background()?;
-            }
-            if self.drawing_line == TOTAL_LINE {
-                self.draw_line(texture)?;
-                self.drawing_line = 0;
-            } else {
-                self.drawing_line += 1;
-            }
-            if self.is_just_in_vblank_line() {
-                self.cpu.bus.cpu_bus.ppu_register.ppu_status.in_vlank = true;
-                if self.cpu.bus.cpu_bus.ppu_register.ppu_ctrl.gen_nmi {
-                    self.cpu.interrupt(Interrupt::Nmi);
-                }
-            } else if self.drawing_line == 0 {
-                self.cpu.bus.cpu_bus.ppu_register.ppu_status.in_vlank = false;
-                self.cpu
-                    .bus
-                    .cpu_bus
-                    .ppu_register
-                    .ppu_status
-                    .false_sprite_zero_hit();
-                self.cpu.set_interrupt(false);
-            }
+        let (numerator, denominator) = self.cpu.bus.ppu.region.ppu_dots_per_cpu_cycle();
+        let dots = self.cpu.cycle * numerator + self.ppu_dot_remainder;
+        self.ppu_dot_remainder = dots % denominator;
+        for _ in 0..dots / denominator {
+            self.ppu_tick(texture)?;
         }
 
         Ok(())
     }
 
-    fn run(&mut self, texture: &mut Texture) -> Result<(), String> {
-        self.cpu_update();
-        self.apu_update();
-        self.ppu_update(texture)?;
-        self.cpu.clear_cycle();
-        Ok(())
-    }
-
-    fn set_secondary_oam_behind_background_for_nomal(&mut self) {
-        let behind_background = true;
-        self.cpu
-            .bus
-            .ppu
-            .set_secondary_oam(self.drawing_line as u8, behind_background);
-    }
-
-    fn set_secondary_oam_front_of_background_for_nomal(&mut self) {
-        let behind_background = false;
-        self.cpu
-            .bus
-            .ppu
-            .set_secondary_oam(self.drawing_line as u8, behind_background);
+    fn is_skipped_dot(&self) -> bool {
+        let ppu = &self.cpu.bus.ppu;
+        ppu.region.has_odd_frame_skip()
+            && ppu.drawing_line == ppu.region.pre_render_line()
+            && ppu.cycle == PPU_DRAW_LINE_CYCLE - 2
+            && ppu.frame % 2 == 1
+            && self.ppu_register().ppu_mask.is_rendering_enabled()
     }
 
-    fn set_secondary_oam_behind_background_on_bottom(&mut self) {
-        let behind_background = true;
-        self.cpu
-            .bus
-            .ppu
-            .set_secondary_oam(self.drawing_line as u8 - 8, behind_background);
-    }
+    // Odd frames drop the last dot of the pre-render line while rendering is enabled.
+    fn inc_ppu_dot(&mut self, texture: &mut Texture) -> Result<(), String> {
+        let is_skipped_dot = self.is_skipped_dot();
+        let ppu = &mut self.cpu.bus.ppu;
+        ppu.cycle += 1;
+        if ppu.cycle >= PPU_DRAW_LINE_CYCLE || is_skipped_dot {
+            ppu.cycle = 0;
+            ppu.drawing_line += 1;
+            if ppu.drawing_line == ppu.region.total_lines() {
+                ppu.drawing_line = 0;
+                ppu.frame += 1;
+                ppu.event_log.end_frame();
+                self.end_frame();
+            } else if ppu.drawing_line == POST_RENDER_LINE {
+                self.draw_line(texture)?;
+                self.sync_audio();
+            }
+        }
 
-    fn set_secondary_oam_front_of_background_on_bottom(&mut self) {
-        let behind_background = false;
-        self.cpu
-            .bus
-            .ppu
-            .set_secondary_oam(self.drawing_line as u8 - 8, behind_background);
+        Ok(())
     }
 
-    pub fn refers_base_nametable(&self) -> (bool, bool) {
-        let base_name_table_addr = (self
-            .cpu
-            .bus
-            .cpu_bus
-            .ppu_register
-            .internal_registers
-            .temporary_vram
-            & 0b0000110000000000)
-            >> 10;
-        match base_name_table_addr {
-            0b00 => (false, false),
-            0b01 => (true, false),
-            0b10 => (false, true),
-            0b11 => (true, true),
-            _ => unreachable!(),
+    fn end_frame(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.end_frame();
+        }
+        if self.is_record_toggle_pending {
+            self.toggle_recording();
         }
     }
 
-    fn build_base_nametable_addr(&self) -> u16 {
-        let mut base_addr = 0x2000;
-        let (additional_x, additional_y) = self.refers_base_nametable();
-
-        if additional_x {
-            base_addr += 0x400;
+    fn ppu_tick(&mut self, texture: &mut Texture) -> Result<(), String> {
+        self.inc_ppu_dot(texture)?;
+        let ppu = &self.cpu.bus.ppu;
+        let (drawing_line, cycle, region) = (ppu.drawing_line, ppu.cycle, ppu.region);
+        if drawing_line < VISIBLE_LINES && cycle == 1 {
+            self.render_line()?;
+        } else if drawing_line == region.vblank_line() && cycle == VBLANK_START_CYCLE {
+            self.start_vblank();
+        } else if drawing_line == region.pre_render_line() && cycle == 1 {
+            self.end_vblank();
         }
-
-        if additional_y {
-            base_addr += 0x800;
+        self.update_scroll(cycle);
+        if drawing_line < VISIBLE_LINES && cycle == 256 {
+            self.evaluate_sprite_overflow();
+        }
+        if self.sprite_zero_hit_dot == Some(cycle) {
+            self.sprite_zero_hit_dot = None;
+            self.cpu
+                .bus
+                .cpu_bus
+                .ppu_register
+                .ppu_status
+                .true_sprite_zero_hit();
         }
 
-        base_addr
+        Ok(())
     }
 
-    fn calc_tile_idx(&self, x: u16, y: u16, data: u16) -> u16 {
-        let mut base_addr = self.build_base_nametable_addr();
-        let mut x = x / 8 + data;
-        let mut y = ((y + self.drawing_line) / 8) * 0x20;
-
-        if x > 0x1F {
-            x -= 0x20;
-            base_addr ^= 0x400;
-            if x > 0x1F {
-                x -= 0x20;
-                base_addr ^= 0x400;
-            }
+    fn evaluate_sprite_overflow(&mut self) {
+        let ppu_register = self.ppu_register();
+        if !ppu_register.ppu_mask.is_rendering_enabled() {
+            return;
         }
-
-        if y > 0x3A0 {
-            y -= 0x3C0;
-            base_addr ^= 0x800;
-            if y > 0x3A0 {
-                y -= 0x3C0;
-                base_addr ^= 0x800;
-            }
+        let height = ppu_register.ppu_ctrl.sprite_height();
+        let ppu = &self.cpu.bus.ppu;
+        if ppu.is_sprite_overflow(ppu.drawing_line, height) {
+            self.cpu
+                .bus
+                .cpu_bus
+                .ppu_register
+                .ppu_status
+                .true_sprite_overflow();
         }
-
-        base_addr + x + y
     }
 
-    // When over 0xFF dot scroll position, refers side nametable continuous.
-    fn refers_tile_nametable(&self, scrolled_x: u16, scrolled_y: u16) -> [usize; 33] {
-        let mut arr: [usize; 33] = [0; 33];
-        for i in 0..33 as u16 {
-            let x = self.calc_tile_idx(scrolled_x, scrolled_y, i);
-            arr[i as usize] = x as usize;
+    fn update_scroll(&mut self, cycle: u16) {
+        if !self.ppu_register().ppu_mask.is_rendering_enabled()
+            || !self.cpu.bus.ppu.is_rendering_line()
+        {
+            return;
+        }
+        let is_pre_render_line =
+            self.cpu.bus.ppu.drawing_line == self.cpu.bus.ppu.region.pre_render_line();
+        let internal_registers = &mut self.cpu.bus.cpu_bus.ppu_register.internal_registers;
+        match cycle {
+            256 => internal_registers.inc_y(),
+            257 => internal_registers.copy_horizontal_bits(),
+            280..=304 if is_pre_render_line => internal_registers.copy_vertical_bits(),
+            _ => (),
         }
-
-        arr
     }
 
-    fn build_background_dot_info(
-        &mut self,
-        background_row: u8,
-        background_high: u8,
-        tile_idx: usize,
-        shift_count: u32,
-        x_per_tile: u32,
-    ) -> (u16, u8, u8) {
-        let idx = {
-            let row_idx = (background_row & (0b1 << shift_count) != 0) as u16;
-            let high_idx = (background_high & (0b1 << shift_count) != 0) as u16;
-            high_idx << 1 | row_idx
-        };
-        let x = (x_per_tile + tile_idx as u32 * 8) as u8;
-        let y = self.drawing_line as u8;
-        (idx, x, y)
+    fn end_vblank(&mut self) {
+        let ppu_status = &mut self.cpu.bus.cpu_bus.ppu_register.ppu_status;
+        ppu_status.in_vlank = false;
+        ppu_status.false_sprite_zero_hit();
+        ppu_status.false_sprite_overflow();
     }
 
-    fn build_left_background_tile(
-        &mut self,
-        nametable: usize,
-        tile_idx: usize,
-        attr_idx: usize,
-        left_x_ratio: u32,
-        scrolled_y: u16,
-    ) {
-        let (background_row, background_high) =
-            self.pick_row_high_tile_background(nametable, scrolled_y);
-        for i in 0..left_x_ratio {
-            let (palette_idx, x, y) = self.build_background_dot_info(
-                background_row,
-                background_high,
-                tile_idx,
-                left_x_ratio - 1 - i,
-                i,
-            );
-            if (attr_idx + palette_idx as usize) != 0
-                && (attr_idx + palette_idx as usize) != 4
-                && (attr_idx + palette_idx as usize) != 8
-                && (attr_idx + palette_idx as usize) != 12
-            {
-                let sprite_color_idx = self.calc_background_color_idx(attr_idx, palette_idx);
-                self.texture_buffer.insert_color(x, y, sprite_color_idx);
-            }
+    fn start_vblank(&mut self) {
+        let ppu = &mut self.cpu.bus.ppu;
+        let (suppress_vblank, suppress_nmi) = (ppu.suppress_vblank, ppu.suppress_nmi);
+        ppu.suppress_vblank = false;
+        ppu.suppress_nmi = false;
+        if !suppress_vblank {
+            self.cpu.bus.cpu_bus.ppu_register.ppu_status.in_vlank = true;
         }
-    }
-
-    fn build_right_background_tile(
-        &mut self,
-        nametable: usize,
-        tile_idx: usize,
-        attr_idx: usize,
-        left_x_ratio: u32,
-        right_x_ratio: u32,
-        scrolled_y: u16,
-    ) {
-        let (background_row, background_high) =
-            self.pick_row_high_tile_background(nametable, scrolled_y);
-        for i in 0..right_x_ratio {
-            let (palette_idx, x, y) = self.build_background_dot_info(
-                background_row,
-                background_high,
-                tile_idx,
-                7 - i,
-                left_x_ratio + i,
-            );
-            if (attr_idx + palette_idx as usize) != 0
-                && (attr_idx + palette_idx as usize) != 4
-                && (attr_idx + palette_idx as usize) != 8
-                && (attr_idx + palette_idx as usize) != 12
-            {
-                let sprite_color_idx = self.calc_background_color_idx(attr_idx, palette_idx);
-                self.texture_buffer.insert_color(x, y, sprite_color_idx);
-            }
+        if !suppress_nmi && self.cpu.bus.cpu_bus.ppu_register.ppu_ctrl.gen_nmi {
+            self.cpu.bus.ppu.nmi_pending = true;
         }
     }
 
-    fn calc_relative_addr_with_base_nametable(&self, nametable: usize) -> usize {
-        let addr = match nametable {
-            0x2000..=0x23BF => nametable - 0x2000,
-            0x2400..=0x27BF => nametable - 0x2400,
-            0x2800..=0x2BBF => nametable - 0x2800,
-            0x2C00..=0x2FBF => nametable - 0x2C00,
-            _ => unreachable!(),
-        };
-        addr & 0xFFF
+    // An NMI raised on the last dots is taken after the next instruction, which
+    // leaves room for a $2002 read to cancel it.
+    fn handle_nmi(&mut self) {
+        if self.cpu.bus.ppu.nmi_pending && !self.cpu.bus.ppu.is_just_after_vblank(0) {
+            self.cpu.bus.ppu.nmi_pending = false;
+            self.cpu.interrupt(Interrupt::Nmi);
+        }
     }
 
-    fn pick_attr_base_addr(&mut self, nametable: usize) -> usize {
-        match nametable {
-            0x2000..=0x23BF => 0x23C0,
-            0x2400..=0x27BF => 0x27C0,
-            0x2800..=0x2BBF => 0x2BC0,
-            0x2C00..=0x2FBF => 0x2FC0,
-            _ => unreachable!(),
+    fn handle_irq(&mut self) {
+        if self.cpu.bus.apu.is_irq() && !self.cpu.get_interrupt() {
+            self.cpu.interrupt(Interrupt::Irq);
         }
     }
 
-    fn build_attr_idx(&mut self, nametable: usize) -> usize {
-        let corner_idx = (nametable & 0x1F) / 4;
-        let addr_relative_with_base_nametable =
-            self.calc_relative_addr_with_base_nametable(nametable);
-
-        let relative_idx =
-            (((addr_relative_with_base_nametable / 0x40) % 2) * 2) + ((nametable & 0x1F) / 2) % 2;
-        let belongs_attr_idx = self.pick_attr_base_addr(nametable)
-            + (addr_relative_with_base_nametable / 0x80) * 8
-            + corner_idx;
-        let belongs_palette = self.cpu.bus.ppu.map.addr(belongs_attr_idx as u16) as usize;
-        ((belongs_palette & (0b11 << relative_idx * 2)) >> (relative_idx * 2)) * 4
+    fn run(&mut self, texture: &mut Texture) -> Result<(), String> {
+        self.cpu_update();
+        self.apu_update();
+        self.ppu_update(texture)?;
+        self.cpu.clear_cycle();
+        self.handle_nmi();
+        self.handle_irq();
+        Ok(())
     }
 
-    fn build_background_tiles(
-        &mut self,
-        tile_nametables: [usize; 33],
-        scrolled_x: u16,
-        scrolled_y: u16,
-    ) {
-        let (left_x_ratio, right_x_ratio) = calc_scrolled_tile_ratio(scrolled_x);
-        for tile_idx in 0..TILE_COUNTS_ON_WIDTH as usize {
-            let (left_nametable, right_nametable) =
-                { (tile_nametables[tile_idx], tile_nametables[tile_idx + 1]) };
-            let (left_attr_idx, right_attr_idx) = (
-                self.build_attr_idx(left_nametable),
-                self.build_attr_idx(right_nametable),
-            );
+    fn set_secondary_oam_behind_background(&mut self) {
+        let behind_background = true;
+        let height = self.ppu_register().ppu_ctrl.sprite_height();
+        self.cpu.bus.ppu.set_secondary_oam(
+            self.cpu.bus.ppu.drawing_line as u8,
+            behind_background,
+            height,
+            self.sprite_limit,
+        );
+    }
 
-            self.build_left_background_tile(
-                left_nametable,
-                tile_idx,
-                left_attr_idx,
-                left_x_ratio,
-                scrolled_y,
-            );
-            self.build_right_background_tile(
-                right_nametable,
-                tile_idx,
-                right_attr_idx,
-                left_x_ratio,
-                right_x_ratio,
-                scrolled_y,
-            );
-        }
+    fn set_secondary_oam_front_of_background(&mut self) {
+        let behind_background = false;
+        let height = self.ppu_register().ppu_ctrl.sprite_height();
+        self.cpu.bus.ppu.set_secondary_oam(
+            self.cpu.bus.ppu.drawing_line as u8,
+            behind_background,
+            height,
+            self.sprite_limit,
+        );
     }
 
     fn calc_background_color_idx(&mut self, attr_idx: usize, pallete_idx: u16) -> usize {
@@ -723,15 +801,17 @@ impl Emulator {
             .ppu_register
             .ppu_mask
             .apply_gray_scale(&mut color_idx);
+        self.cpu
+            .bus
+            .cpu_bus
+            .ppu_register
+            .ppu_mask
+            .apply_emphasis(&mut color_idx);
         color_idx
     }
 
-    fn pick_row_high_tile_background(
-        &mut self,
-        tile_nametable: usize,
-        scrolled_y: u16,
-    ) -> (u8, u8) {
-        let background_idx = self.cpu.bus.ppu.map.addr(tile_nametable as u16) as u16;
+    fn pick_row_high_tile_background(&mut self, tile_addr: u16, fine_y: u16) -> (u8, u8) {
+        let background_idx = self.cpu.bus.ppu.map.addr(tile_addr) as u16;
         let deep_idx = 0x1000
             * self
                 .cpu
@@ -741,35 +821,12 @@ impl Emulator {
                 .ppu_ctrl
                 .is_deep_bk_index() as u16;
 
-        let base_addr =
-            background_idx as u16 * 0x10 + (scrolled_y + self.drawing_line) % 8 + deep_idx;
+        let base_addr = background_idx * 0x10 + fine_y + deep_idx;
         let row = self.cpu.bus.ppu.map.addr(base_addr);
         let high = self.cpu.bus.ppu.map.addr(base_addr + 0x8);
         (row, high)
     }
 
-    fn get_scroll_addrs(&self) -> (u16, u16) {
-        let ppu_register = &self.cpu.bus.cpu_bus.ppu_register;
-
-        let scrolled_x = {
-            let l_data = ppu_register.internal_registers.x_scroll;
-            let h_data = (ppu_register.internal_registers.temporary_vram & 0b00011111) << 3;
-            h_data | l_data as u16
-        };
-
-        let scrolled_y = {
-            let bottom_data =
-                (ppu_register.internal_registers.temporary_vram & 0b0111000000000000) >> 12;
-            let middle_data =
-                ((ppu_register.internal_registers.temporary_vram & 0b011100000) >> 5) << 3;
-            let high_data =
-                ((ppu_register.internal_registers.temporary_vram & 0b0000001100000000) >> 8) << 6;
-
-            high_data | middle_data | bottom_data
-        };
-        (scrolled_x, scrolled_y)
-    }
-
     fn insert_universal_background(&mut self) {
         if self
             .cpu
@@ -786,36 +843,56 @@ impl Emulator {
                 .ppu_register
                 .ppu_mask
                 .apply_gray_scale(&mut color_idx);
+            self.cpu
+                .bus
+                .cpu_bus
+                .ppu_register
+                .ppu_mask
+                .apply_emphasis(&mut color_idx);
             for tile_idx in 0..TILE_COUNTS_ON_WIDTH as usize {
                 for x in 0..8 {
-                    self.texture_buffer.insert_color(
-                        tile_idx as u8 * 8 + x,
-                        self.drawing_line as u8,
-                        color_idx,
-                    );
+                    self.line_buffer[tile_idx * 8 + x] = color_idx as u16;
                 }
             }
         }
     }
 
-    fn insert_front_background(&mut self) {
-        if self
-            .cpu
-            .bus
-            .cpu_bus
-            .ppu_register
-            .ppu_mask
-            .is_show_background()
-        {
-            let (scrolled_x, scrolled_y) = self.get_scroll_addrs();
-            let tile_nametables = self.refers_tile_nametable(scrolled_x, scrolled_y);
-
-            self.build_background_tiles(tile_nametables, scrolled_x, scrolled_y);
-        }
+    fn build_attr_idx(&mut self, internal_registers: &InternalRegisters) -> usize {
+        let attr = self.cpu.bus.ppu.map.addr(internal_registers.attr_addr());
+        ((attr >> internal_registers.attr_shift()) & 0b11) as usize * 4
     }
 
-    fn is_just_in_vblank_line(&self) -> bool {
-        self.drawing_line == VBLANK_LINE
+    // Fetches 33 tiles from v so the fine X scroll can shift in the partial tile on
+    // the right. v itself only moves at dots 256 and 257, so a copy walks the line.
+    fn insert_front_background(&mut self) {
+        if !self.ppu_register().ppu_mask.is_show_background() {
+            return;
+        }
+        let mut internal_registers = self.ppu_register().internal_registers.clone();
+        let fine_x = internal_registers.x_scroll as usize;
+        let fine_y = internal_registers.fine_y();
+        for tile_idx in 0..TILE_COUNTS_ON_WIDTH + 1 {
+            let attr_idx = self.build_attr_idx(&internal_registers);
+            let (background_row, background_high) =
+                self.pick_row_high_tile_background(internal_registers.tile_addr(), fine_y);
+            for i in 0..8 {
+                let x = match (tile_idx * 8 + i).checked_sub(fine_x) {
+                    Some(x) if x < 256 => x as u8,
+                    _ => continue,
+                };
+                let palette_idx = {
+                    let row_idx = (background_row & (0b1 << (7 - i)) != 0) as u16;
+                    let high_idx = (background_high & (0b1 << (7 - i)) != 0) as u16;
+                    high_idx << 1 | row_idx
+                };
+                if palette_idx != 0 && self.ppu_register().ppu_mask.is_show_background_at(x) {
+                    let color_idx = self.calc_background_color_idx(attr_idx, palette_idx);
+                    self.line_buffer[x as usize] = color_idx as u16;
+                    self.background_opaque[x as usize] = true;
+                }
+            }
+            internal_registers.inc_coarse_x();
+        }
     }
 
     pub fn set_sprites(&mut self, chars: &Vec<u8>) {
@@ -827,170 +904,58 @@ impl Emulator {
         }
     }
 
-    fn insert_sprites_for_big_top(&mut self) -> Result<(), String> {
-        Ok(self.insert_big_size_sprites(false)?)
-    }
-
-    fn insert_sprites_for_big_bottom(&mut self) -> Result<(), String> {
-        Ok(self.insert_big_size_sprites(true)?)
-    }
+    fn find_sprite_zero_hit_dot(&mut self) -> Option<u16> {
+        let ppu_register = self.ppu_register();
+        if !ppu_register.ppu_mask.is_show_background()
+            || !ppu_register.ppu_mask.is_show_sprites()
+            || ppu_register.ppu_status.is_occured_sprite_zero_hit()
+        {
+            return None;
+        }
+        let is_clipping_leftmost = ppu_register.ppu_mask.is_clipping_leftmost();
+        let height = ppu_register.ppu_ctrl.sprite_height();
+        let sprite_ptn_table_addr = ppu_register.ppu_ctrl.sprite_ptn_table_addr;
 
-    fn insert_big_size_color(
-        &mut self,
-        sprite_info: &SpriteInfo,
-        pallet_base_idx: usize,
-        sprite_row: u8,
-        sprite_high: u8,
-        dot_index_per_sprite: u8,
-        color_info: &mut FxHashMap<u8, usize>,
-        is_bottom: bool,
-    ) {
-        if let Some((idx, x)) = if is_bottom {
-            self.build_big_bottom_dot_info(
-                &sprite_info,
+        let sprite_zero = self.cpu.bus.ppu.primary_oam.sprite_infos[0].clone();
+        if !sprite_zero.in_drawing_range(self.cpu.bus.ppu.drawing_line as u8, height) {
+            return None;
+        }
+        let base_addr = sprite_zero.pattern_addr(
+            sprite_zero.relative_hight(self.cpu.bus.ppu.drawing_line),
+            height,
+            sprite_ptn_table_addr,
+        );
+        let (sprite_row, sprite_high) = self.sprite_addr(base_addr);
+        for dot_index_per_sprite in 0..8 {
+            if let Some((_, x)) = self.build_sprite_dot_info(
+                &sprite_zero,
                 sprite_row,
                 sprite_high,
                 dot_index_per_sprite,
-            )
-        } else {
-            self.build_normal_dot_info(&sprite_info, sprite_row, sprite_high, dot_index_per_sprite)
-        } {
-            let pallet_idx = pallet_base_idx + idx as usize;
-            let mut color_idx = self.cpu.bus.ppu.map.sprite_pallet[pallet_idx] as usize;
-            self.ppu_register()
-                .ppu_mask
-                .apply_gray_scale(&mut color_idx);
-
-            if !color_info.contains_key(&x) {
-                color_info.insert(x, color_idx);
-            }
-
-            if self.is_in_sprite_zero_hit_condition(&sprite_info, pallet_idx) {
-                self.set_sprite_zero_hit_flag();
-            }
-        } else {
-            return;
-        };
-    }
-
-    fn insert_big_size_sprites(&mut self, is_bottom: bool) -> Result<(), String> {
-        let mut color_info: FxHashMap<u8, usize> = FxHashMap::default();
-        for n in 0..TILE_COUNTS_ON_WIDTH * 8 {
-            if let Some(sprite_info) = self
-                .cpu
-                .bus
-                .ppu
-                .secondary_oam
-                .pick_sprite_info_with_x(n as u8)
-            {
-                let sprite_info = sprite_info.clone();
-                let relative_hight = (self.drawing_line - sprite_info.pos_y as u16) % 8;
-                let base_addr = (((sprite_info.tile_index.tile_number + 1) % 2) == 0) as u16
-                    * 0x1000
-                    + ((sprite_info.tile_index.tile_number as u16) / 2) * 0x20
-                    + relative_hight;
-                let (sprite_row, sprite_high) = {
-                    if is_bottom {
-                        self.big_size_botoom_sprite_addr(base_addr)
-                    } else {
-                        self.normal_size_sprite_addr(base_addr)
-                    }
-                };
-                let pallet_base_idx = (sprite_info.attr.palette * 4) as usize;
-                let for_count = if is_bottom { 16 } else { 8 };
-                for dot_index_per_sprite in for_count - 8..for_count {
-                    self.insert_big_size_color(
-                        &sprite_info,
-                        pallet_base_idx,
-                        sprite_row,
-                        sprite_high,
-                        dot_index_per_sprite,
-                        &mut color_info,
-                        is_bottom,
-                    );
+            ) {
+                if x == 255 || (x < 8 && is_clipping_leftmost) {
+                    continue;
                 }
-            } else {
-                continue;
-            };
-        }
-        self.texture_buffer
-            .insert_colors(color_info, self.drawing_line as u8);
-        Ok(())
-    }
-
-    fn is_in_sprite_zero_hit_condition(
-        &mut self,
-        sprite_info: &SpriteInfo,
-        pallet_idx: usize,
-    ) -> bool {
-        !self.ppu_register().ppu_status.is_occured_sprite_zero_hit()
-            && sprite_info.attr.priority
-            && pallet_idx % 4 != 0
-    }
-
-    fn set_sprite_zero_hit_flag(&mut self) {
-        if self.drawing_line == self.ppu_register().ppu_status.line_occured_sprite_zero_hit + 1 {
-            self.cpu
-                .bus
-                .cpu_bus
-                .ppu_register
-                .ppu_status
-                .true_sprite_zero_hit();
-        } else {
-            self.cpu
-                .bus
-                .cpu_bus
-                .ppu_register
-                .ppu_status
-                .set_line_occured_sprite_zero_hit(self.drawing_line)
+                if self.background_opaque[x as usize] {
+                    return Some(x as u16 + 1);
+                }
+            }
         }
+        None
     }
 
-    fn big_size_botoom_sprite_addr(&mut self, addr: u16) -> (u8, u8) {
-        (
-            self.cpu.bus.ppu.map.addr(addr + 0x10),
-            self.cpu.bus.ppu.map.addr(addr + 0x18),
-        )
-    }
-
-    fn normal_size_sprite_addr(&mut self, addr: u16) -> (u8, u8) {
+    fn sprite_addr(&mut self, addr: u16) -> (u8, u8) {
         (
             self.cpu.bus.ppu.map.addr(addr),
             self.cpu.bus.ppu.map.addr(addr + 8),
         )
     }
 
-    fn ppu_register(&mut self) -> &PpuRegister {
+    fn ppu_register(&self) -> &PpuRegister {
         &self.cpu.bus.cpu_bus.ppu_register
     }
 
-    fn build_big_bottom_dot_info(
-        &mut self,
-        sprite_info: &SpriteInfo,
-        sprite_row: u8,
-        sprite_high: u8,
-        dot_index_per_sprite: u8,
-    ) -> Option<(u16, u8)> {
-        let idx = {
-            let i = if sprite_info.attr.flip_sprite_horizontally {
-                dot_index_per_sprite
-            } else {
-                15 - dot_index_per_sprite
-            };
-
-            let r = (sprite_row & (0b1 << i) != 0) as u16;
-            let h = (sprite_high & (0b1 << i) != 0) as u16;
-            let idx = h << 1 | r;
-            if idx == 0 {
-                return None;
-            }
-            idx
-        };
-        let x = sprite_info.pos_x.wrapping_add(dot_index_per_sprite - 8);
-        Some((idx, x))
-    }
-
-    fn build_normal_dot_info(
+    fn build_sprite_dot_info(
         &mut self,
         sprite_info: &SpriteInfo,
         sprite_row: u8,
@@ -1011,79 +976,65 @@ impl Emulator {
             }
             idx
         };
-        let x = sprite_info.pos_x.wrapping_add(dot_index_per_sprite);
+        let x = sprite_info.pos_x.checked_add(dot_index_per_sprite)?;
         Some((idx, x))
     }
 
-    fn insert_normal_size_color(
+    fn insert_sprite_color(
         &mut self,
         sprite_info: &SpriteInfo,
         pallet_base_idx: usize,
         sprite_row: u8,
         sprite_high: u8,
         dot_index_per_sprite: u8,
-        color_info: &mut FxHashMap<u8, usize>,
+        sprite_drawn: &mut [bool; 256],
     ) {
         if let Some((idx, x)) =
-            self.build_normal_dot_info(sprite_info, sprite_row, sprite_high, dot_index_per_sprite)
+            self.build_sprite_dot_info(sprite_info, sprite_row, sprite_high, dot_index_per_sprite)
         {
+            if !self.ppu_register().ppu_mask.is_show_sprites_at(x) {
+                return;
+            }
             let pallet_idx = pallet_base_idx + idx as usize;
             let mut color_idx = self.cpu.bus.ppu.map.sprite_pallet[pallet_idx] as usize;
             self.ppu_register()
                 .ppu_mask
                 .apply_gray_scale(&mut color_idx);
+            self.ppu_register().ppu_mask.apply_emphasis(&mut color_idx);
 
-            if !color_info.contains_key(&x) {
-                color_info.insert(x, color_idx);
-            }
-
-            if self.is_in_sprite_zero_hit_condition(&sprite_info, pallet_idx) {
-                self.set_sprite_zero_hit_flag();
+            if !sprite_drawn[x as usize] {
+                sprite_drawn[x as usize] = true;
+                self.line_buffer[x as usize] = color_idx as u16;
             }
         } else {
             return;
         };
     }
 
-    fn insert_normal_size_sprites(&mut self) -> Result<(), String> {
-        let mut color_info: FxHashMap<u8, usize> = FxHashMap::default();
-        for n in 0..TILE_COUNTS_ON_WIDTH * 8 {
-            if let Some(sprite_info) = self
-                .cpu
-                .bus
-                .ppu
-                .secondary_oam
-                .pick_sprite_info_with_x(n as u8)
-            {
-                let sprite_info = sprite_info.clone();
-                let relative_hight = (self.drawing_line - sprite_info.pos_y as u16) % 8;
-                let base_addr = sprite_info.tile_index.bank_of_tile as u16 * 0x1000
-                    + self.ppu_register().ppu_ctrl.sprite_ptn_table_addr as u16 * 0x1000
-                    + (sprite_info.tile_index.tile_number as u16) * 0x10
-                    + if sprite_info.attr.flip_sprite_vertically {
-                        7 - relative_hight
-                    } else {
-                        relative_hight
-                    };
-                let (sprite_row, sprite_high) = self.normal_size_sprite_addr(base_addr);
-                let pallet_base_idx = (sprite_info.attr.palette * 4) as usize;
-                for dot_index_per_sprite in 0..8 {
-                    self.insert_normal_size_color(
-                        &sprite_info,
-                        pallet_base_idx,
-                        sprite_row,
-                        sprite_high,
-                        dot_index_per_sprite,
-                        &mut color_info,
-                    );
-                }
-            } else {
-                continue;
+    // Sprites are drawn in OAM order, so the lower index wins where they overlap.
+    fn insert_sprites(&mut self) -> Result<(), String> {
+        let mut sprite_drawn = [false; 256];
+        for sprite_info in self.cpu.bus.ppu.secondary_oam.sprite_infos.clone() {
+            let relative_hight = sprite_info.relative_hight(self.cpu.bus.ppu.drawing_line);
+            let ppu_ctrl = &self.ppu_register().ppu_ctrl;
+            let base_addr = sprite_info.pattern_addr(
+                relative_hight,
+                ppu_ctrl.sprite_height(),
+                ppu_ctrl.sprite_ptn_table_addr,
+            );
+            let (sprite_row, sprite_high) = self.sprite_addr(base_addr);
+            let pallet_base_idx = (sprite_info.attr.palette * 4) as usize;
+            for dot_index_per_sprite in 0..8 {
+                self.insert_sprite_color(
+                    &sprite_info,
+                    pallet_base_idx,
+                    sprite_row,
+                    sprite_high,
+                    dot_index_per_sprite,
+                    &mut sprite_drawn,
+                );
             }
         }
-
-        self.texture_buffer
-            .insert_colors(color_info, self.drawing_line as u8);
         Ok(())
     }
 }
@@ -0,0 +1,152 @@
+use crate::emulator::palette::{Palette, PaletteSettings};
+use crate::ppu::frame_buffer::{FRAME_HEIGHT, FRAME_WIDTH};
+
+pub const NTSC_WIDTH: usize = 640;
+const SAMPLES_PER_DOT: usize = 8;
+const SAMPLES_PER_LINE: usize = FRAME_WIDTH * SAMPLES_PER_DOT;
+const CHROMA_PERIOD: usize = 12;
+// A full line of 341 dots leaves the subcarrier 4 samples further along.
+const LINE_PHASE_SHIFT: usize = 341 * SAMPLES_PER_DOT % CHROMA_PERIOD;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSettings {
+    pub sharpness: f32,
+    pub artifacts: f32,
+    pub palette: PaletteSettings,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NtscSettings {
+    pub fn new() -> Self {
+        Self {
+            sharpness: 0.5,
+            artifacts: 1.0,
+            palette: PaletteSettings::new(),
+        }
+    }
+
+    pub fn from_args(args: &[String]) -> Self {
+        let mut settings = Self {
+            palette: PaletteSettings::from_args(args),
+            ..Self::new()
+        };
+        for arg in args {
+            let (key, value) = match arg.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let field = match key {
+                "sharpness" => &mut settings.sharpness,
+                "artifacts" => &mut settings.artifacts,
+                _ => continue,
+            };
+            *field = value
+                .parse::<f32>()
+                .unwrap_or_else(|_| panic!("Invalid {} value {}.", key, value))
+                .clamp(0.0, 1.0);
+        }
+        settings
+    }
+}
+
+pub struct NtscFilter {
+    pub settings: NtscSettings,
+    pub buffer: Vec<u8>,
+    signal: Vec<f32>,
+    clean_yiq: Vec<[f32; 3]>,
+    carrier: [(f32, f32); CHROMA_PERIOD],
+}
+
+impl NtscFilter {
+    pub fn new(settings: NtscSettings) -> Self {
+        let clean_yiq = (0..512)
+            .map(|n| Palette::composite_yiq(n & 0x3F, n >> 6, &settings.palette))
+            .collect();
+        let mut carrier = [(0.0, 0.0); CHROMA_PERIOD];
+        for (phase, wave) in carrier.iter_mut().enumerate() {
+            let angle = Palette::chroma_angle(phase, &settings.palette);
+            *wave = (angle.cos(), angle.sin());
+        }
+        Self {
+            settings,
+            buffer: vec![0; NTSC_WIDTH * FRAME_HEIGHT * 3],
+            signal: vec![0.0; SAMPLES_PER_LINE],
+            clean_yiq,
+            carrier,
+        }
+    }
+
+    // The subcarrier drifts by a third of a cycle every frame, which makes chroma crawl.
+    pub fn apply(&mut self, indices: &[u16], frame: u64) -> &[u8] {
+        let frame_phase = (frame % 3) as usize * LINE_PHASE_SHIFT;
+        for (y, line) in indices.chunks(FRAME_WIDTH).enumerate() {
+            let line_phase = (frame_phase + y * LINE_PHASE_SHIFT) % CHROMA_PERIOD;
+            self.encode_line(line, line_phase);
+            self.decode_line(line, line_phase, y);
+        }
+        &self.buffer
+    }
+
+    fn encode_line(&mut self, line: &[u16], line_phase: usize) {
+        for (n, signal) in self.signal.iter_mut().enumerate() {
+            let idx = line[n / SAMPLES_PER_DOT] as usize;
+            let phase = (line_phase + n) % CHROMA_PERIOD;
+            *signal = Palette::composite_signal(idx & 0x3F, idx >> 6, phase);
+        }
+    }
+
+    fn sample_range(center: usize, width: usize) -> std::ops::Range<usize> {
+        let start = center.saturating_sub(width / 2);
+        start..(start + width).min(SAMPLES_PER_LINE)
+    }
+
+    fn average_luma(&self, line: &[u16], center: usize, width: usize) -> (f32, f32) {
+        let range = Self::sample_range(center, width);
+        let len = range.len() as f32;
+        let (mut luma, mut clean_luma) = (0.0, 0.0);
+        for n in range {
+            luma += self.signal[n];
+            clean_luma += self.clean_yiq[line[n / SAMPLES_PER_DOT] as usize][0];
+        }
+        (luma / len, clean_luma / len)
+    }
+
+    // Luma and chroma both average one subcarrier cycle and so pick up the neighbouring
+    // dots, which is where the artifacts come from.
+    fn decode_line(&mut self, line: &[u16], line_phase: usize, y: usize) {
+        let settings = self.settings;
+        for x in 0..NTSC_WIDTH {
+            let center = (x * SAMPLES_PER_LINE + SAMPLES_PER_LINE / 2) / NTSC_WIDTH;
+
+            let (luma, clean_luma) = self.average_luma(line, center, CHROMA_PERIOD);
+            let (soft_luma, soft_clean_luma) = self.average_luma(line, center, CHROMA_PERIOD * 2);
+            let luma = luma + (luma - soft_luma) * settings.sharpness;
+            let clean_luma = clean_luma + (clean_luma - soft_clean_luma) * settings.sharpness;
+
+            let (mut i, mut q) = (0.0, 0.0);
+            for n in Self::sample_range(center, CHROMA_PERIOD) {
+                let (cos, sin) = self.carrier[(line_phase + n) % CHROMA_PERIOD];
+                i += self.signal[n] * cos;
+                q += self.signal[n] * sin;
+            }
+            let (i, q) = (i / CHROMA_PERIOD as f32, q / CHROMA_PERIOD as f32);
+
+            let clean = self.clean_yiq[line[center / SAMPLES_PER_DOT] as usize];
+            let blend =
+                |clean: f32, composite: f32| clean + (composite - clean) * settings.artifacts;
+            let yiq = [
+                blend(clean_luma, luma),
+                blend(clean[1], i),
+                blend(clean[2], q),
+            ];
+            let offset = (y * NTSC_WIDTH + x) * 3;
+            self.buffer[offset..offset + 3]
+                .copy_from_slice(&Palette::yiq_to_rgb(yiq, &settings.palette));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+const DEFAULT_COLORS: [[u8; 3]; 64] = [
+    [0x80, 0x80, 0x80],
+    [0x00, 0x3D, 0xA6],
+    [0x00, 0x12, 0xB0],
+    [0x44, 0x00, 0x96],
+    [0xA1, 0x00, 0x5E],
+    [0xC7, 0x00, 0x28],
+    [0xBA, 0x06, 0x00],
+    [0x8C, 0x17, 0x00],
+    [0x5C, 0x2F, 0x00],
+    [0x10, 0x45, 0x00],
+    [0x05, 0x4A, 0x00],
+    [0x00, 0x47, 0x2E],
+    [0x00, 0x41, 0x66],
+    [0x00, 0x00, 0x00],
+    [0x05, 0x05, 0x05],
+    [0x05, 0x05, 0x05],
+    [0xC7, 0xC7, 0xC7],
+    [0x00, 0x77, 0xFF],
+    [0x21, 0x55, 0xFF],
+    [0x82, 0x37, 0xFA],
+    [0xEB, 0x2F, 0xB5],
+    [0xFF, 0x29, 0x50],
+    [0xFF, 0x22, 0x00],
+    [0xD6, 0x32, 0x00],
+    [0xC4, 0x62, 0x00],
+    [0x35, 0x80, 0x00],
+    [0x05, 0x8F, 0x00],
+    [0x00, 0x8A, 0x55],
+    [0x00, 0x99, 0xCC],
+    [0x21, 0x21, 0x21],
+    [0x09, 0x09, 0x09],
+    [0x09, 0x09, 0x09],
+    [0xFF, 0xFF, 0xFF],
+    [0x0F, 0xD7, 0xFF],
+    [0x69, 0xA2, 0xFF],
+    [0xD4, 0x80, 0xFF],
+    [0xFF, 0x45, 0xF3],
+    [0xFF, 0x61, 0x8B],
+    [0xFF, 0x88, 0x33],
+    [0xFF, 0x9C, 0x12],
+    [0xFA, 0xBC, 0x20],
+    [0x9F, 0xE3, 0x0E],
+    [0x2B, 0xF0, 0x35],
+    [0x0C, 0xF0, 0xA4],
+    [0x05, 0xFB, 0xFF],
+    [0x5E, 0x5E, 0x5E],
+    [0x0D, 0x0D, 0x0D],
+    [0x0D, 0x0D, 0x0D],
+    [0xFF, 0xFF, 0xFF],
+    [0xA6, 0xFC, 0xFF],
+    [0xB3, 0xEC, 0xFF],
+    [0xDA, 0xAB, 0xEB],
+    [0xFF, 0xA8, 0xF9],
+    [0xFF, 0xAB, 0xB3],
+    [0xFF, 0xD2, 0xB0],
+    [0xFF, 0xEF, 0xA6],
+    [0xFF, 0xF7, 0x9C],
+    [0xD7, 0xE8, 0x95],
+    [0xA6, 0xED, 0xAF],
+    [0xA2, 0xF2, 0xDA],
+    [0x99, 0xFF, 0xFC],
+    [0xDD, 0xDD, 0xDD],
+    [0x11, 0x11, 0x11],
+    [0x11, 0x11, 0x11],
+];
+
+// Composite voltages per luma level for the low and high half of the colour wave.
+const SIGNAL_LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+const SIGNAL_HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+const SIGNAL_BLACK: f32 = 0.312;
+const SIGNAL_WHITE: f32 = 1.100;
+const COLOR_BURST_PHASE: f32 = 120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaletteSettings {
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl Default for PaletteSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PaletteSettings {
+    pub fn new() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+        }
+    }
+
+    pub fn from_args(args: &[String]) -> Self {
+        let mut settings = Self::new();
+        for arg in args {
+            let (key, value) = match arg.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let field = match key {
+                "hue" => &mut settings.hue,
+                "saturation" => &mut settings.saturation,
+                "contrast" => &mut settings.contrast,
+                "brightness" => &mut settings.brightness,
+                "gamma" => &mut settings.gamma,
+                _ => continue,
+            };
+            *field = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid {} value {}.", key, value));
+        }
+        settings
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [[u8; 3]; 512],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self {
+            name: "default".to_string(),
+            colors: Self::build_emphasis_colors(&DEFAULT_COLORS),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut buffer = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut buffer))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_bytes(&path.display().to_string(), &buffer)
+    }
+
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> Result<Self, String> {
+        let mut colors = [[0; 3]; 512];
+        match bytes.len() {
+            192 => {
+                let mut base_colors = [[0; 3]; 64];
+                for (color, rgb) in base_colors.iter_mut().zip(bytes.chunks(3)) {
+                    color.copy_from_slice(rgb);
+                }
+                colors = Self::build_emphasis_colors(&base_colors);
+            }
+            1536 => {
+                for (color, rgb) in colors.iter_mut().zip(bytes.chunks(3)) {
+                    color.copy_from_slice(rgb);
+                }
+            }
+            n => {
+                return Err(format!(
+                    "{}: palette must be 192 or 1536 bytes, got {}.",
+                    name, n
+                ))
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    pub fn generate(settings: &PaletteSettings) -> Self {
+        let mut colors = [[0; 3]; 512];
+        for (n, color) in colors.iter_mut().enumerate() {
+            let yiq = Self::composite_yiq(n & 0x3F, n >> 6, settings);
+            *color = Self::yiq_to_rgb(yiq, settings);
+        }
+        Self {
+            name: "ntsc".to_string(),
+            colors,
+        }
+    }
+
+    fn build_emphasis_colors(colors: &[[u8; 3]; 64]) -> [[u8; 3]; 512] {
+        let mut emphasis_colors = [[0; 3]; 512];
+        for emphasis in 0..8 {
+            for (n, color) in colors.iter().enumerate() {
+                let mut emphasis_color = *color;
+                if emphasis != 0 {
+                    for (channel, value) in emphasis_color.iter_mut().enumerate() {
+                        if emphasis & (1 << channel) == 0 {
+                            *value = (*value as f32 * EMPHASIS_ATTENUATION) as u8;
+                        }
+                    }
+                }
+                emphasis_colors[(emphasis << 6) | n] = emphasis_color;
+            }
+        }
+        emphasis_colors
+    }
+
+    fn is_in_color_phase(hue: usize, phase: usize) -> bool {
+        (hue + phase) % 12 < 6
+    }
+
+    pub(crate) fn composite_signal(color: usize, emphasis: usize, phase: usize) -> f32 {
+        let (level, hue) = ((color >> 4) & 3, color & 0x0F);
+        let (low, high) = match hue {
+            0x00 => (SIGNAL_HIGH[level], SIGNAL_HIGH[level]),
+            0x0D => (SIGNAL_LOW[level], SIGNAL_LOW[level]),
+            0x0E | 0x0F => (SIGNAL_BLACK, SIGNAL_BLACK),
+            _ => (SIGNAL_LOW[level], SIGNAL_HIGH[level]),
+        };
+        let mut signal = if Self::is_in_color_phase(hue, phase) {
+            high
+        } else {
+            low
+        };
+        // Emphasis pulls the signal down while the wave is in the red, green or blue phase.
+        let is_emphasized = (emphasis & 0b001 != 0 && Self::is_in_color_phase(0x0C, phase))
+            || (emphasis & 0b010 != 0 && Self::is_in_color_phase(0x04, phase))
+            || (emphasis & 0b100 != 0 && Self::is_in_color_phase(0x08, phase));
+        if is_emphasized && hue < 0x0E {
+            signal *= EMPHASIS_ATTENUATION;
+        }
+        (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+    }
+
+    pub(crate) fn chroma_angle(phase: usize, settings: &PaletteSettings) -> f32 {
+        PI * phase as f32 / 6.0 + (COLOR_BURST_PHASE + settings.hue).to_radians()
+    }
+
+    pub(crate) fn composite_yiq(
+        color: usize,
+        emphasis: usize,
+        settings: &PaletteSettings,
+    ) -> [f32; 3] {
+        let mut yiq = [0.0; 3];
+        for phase in 0..12 {
+            let signal = Self::composite_signal(color, emphasis, phase) / 12.0;
+            let angle = Self::chroma_angle(phase, settings);
+            yiq[0] += signal;
+            yiq[1] += signal * angle.cos();
+            yiq[2] += signal * angle.sin();
+        }
+        yiq
+    }
+
+    pub(crate) fn yiq_to_rgb(yiq: [f32; 3], settings: &PaletteSettings) -> [u8; 3] {
+        let y = yiq[0] * settings.contrast + settings.brightness;
+        let i = yiq[1] * settings.saturation * settings.contrast;
+        let q = yiq[2] * settings.saturation * settings.contrast;
+        let rgb = [
+            y + 0.946882 * i + 0.623557 * q,
+            y - 0.274788 * i - 0.635691 * q,
+            y - 1.108545 * i + 1.709007 * q,
+        ];
+        rgb.map(|value| (value.clamp(0.0, 1.0).powf(1.0 / settings.gamma) * 255.0).round() as u8)
+    }
+}
+
+pub struct Palettes {
+    list: Vec<Palette>,
+    selected: usize,
+}
+
+impl Palettes {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut list = vec![
+            Palette::new(),
+            Palette::generate(&PaletteSettings::from_args(args)),
+        ];
+        let selected = match args.iter().find_map(|arg| arg.strip_prefix("palette=")) {
+            None | Some("default") => 0,
+            Some("ntsc") => 1,
+            Some(path) => {
+                list.push(Palette::load(Path::new(path))?);
+                list.len() - 1
+            }
+        };
+        Ok(Self { list, selected })
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.list[self.selected]
+    }
+
+    pub fn select_next(&mut self) -> &Palette {
+        self.selected = (self.selected + 1) % self.list.len();
+        self.current()
+    }
+}
@@ -0,0 +1,176 @@
+use crate::apu::mixer::Channel;
+use crate::apu::APU;
+use crate::apu::SAMPLE_RATE;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// The sizes in the header are patched on finish.
+pub struct WavWriter {
+    writer: Option<BufWriter<File>>,
+    sample_count: u32,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(&Self::header(0))
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            writer: Some(writer),
+            sample_count: 0,
+        })
+    }
+
+    fn header(sample_count: u32) -> Vec<u8> {
+        let data_size = sample_count * 2;
+        let mut header = Vec::with_capacity(Self::HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(Self::HEADER_SIZE - 8 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        header.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+        header
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            writer
+                .write_all(&value.to_le_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        self.sample_count += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<(), String> {
+        let mut writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        writer.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        writer
+            .write_all(&Self::header(self.sample_count))
+            .map_err(|e| e.to_string())?;
+        writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// Channel files go next to path as [stem]_[channel].wav.
+pub struct Recorder {
+    path: PathBuf,
+    writers: Vec<WavWriter>,
+    track_samples: Vec<Vec<f32>>,
+    frames: u32,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, is_record_channels: bool) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut writers = vec![WavWriter::create(path)?];
+        if is_record_channels {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            for channel in Channel::ALL.iter() {
+                let name = format!("{}_{}.wav", stem, channel.name());
+                writers.push(WavWriter::create(&path.with_file_name(name))?);
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            track_samples: vec![Vec::new(); writers.len()],
+            writers,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_record_channels(&self) -> bool {
+        self.writers.len() > 1
+    }
+
+    pub fn write(&mut self, apu: &mut APU) -> Result<(), String> {
+        apu.read_record_samples(&mut self.track_samples);
+        for (writer, samples) in self.writers.iter_mut().zip(self.track_samples.iter_mut()) {
+            writer.write(samples)?;
+            samples.clear();
+        }
+        Ok(())
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    // Returns the recorded frame and sample counts.
+    pub fn finish(mut self) -> Result<(u32, u32), String> {
+        for writer in self.writers.iter_mut() {
+            writer.finish()?;
+        }
+        Ok((self.frames, self.writers[0].sample_count))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::emulator::recorder::*;
+    use std::{env, fs};
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_describes_16_bit_mono_pcm() {
+        let header = WavWriter::header(100);
+        assert_eq!(header.len(), WavWriter::HEADER_SIZE as usize);
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(u32_at(&header, 4), 36 + 200);
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(header[20..24], [1, 0, 1, 0]);
+        assert_eq!(u32_at(&header, 24), SAMPLE_RATE);
+        assert_eq!(u32_at(&header, 28), SAMPLE_RATE * 2);
+        assert_eq!(header[32..36], [2, 0, 16, 0]);
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(u32_at(&header, 40), 200);
+    }
+
+    #[test]
+    fn finish_patches_sizes_into_header() {
+        let path = env::temp_dir().join("fc2_recorder_test.wav");
+        let mut writer = WavWriter::create(&path).unwrap();
+        writer.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        writer.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bytes[..44], WavWriter::header(4)[..]);
+        assert_eq!(bytes[44..], [0, 0, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+    }
+}
@@ -1,9 +1,9 @@
-use crate::emulator::configure::TILE_COUNTS_ON_WIDTH;
-use rustc_hash::*;
+use crate::emulator::palette::Palette;
+use crate::ppu::frame_buffer::FrameBuffer;
 
 pub struct TextureBuffer {
     pub buffer: [u8; 184320],
-    colors: [[u8; 3]; 64],
+    colors: [[u8; 3]; 512],
 }
 
 impl Default for TextureBuffer {
@@ -15,98 +15,18 @@ impl Default for TextureBuffer {
 impl TextureBuffer {
     fn new() -> Self {
         let buffer: [u8; 184320] = [0; 184320];
-
-        let colors = [
-            [0x80, 0x80, 0x80],
-            [0x00, 0x3D, 0xA6],
-            [0x00, 0x12, 0xB0],
-            [0x44, 0x00, 0x96],
-            [0xA1, 0x00, 0x5E],
-            [0xC7, 0x00, 0x28],
-            [0xBA, 0x06, 0x00],
-            [0x8C, 0x17, 0x00],
-            [0x5C, 0x2F, 0x00],
-            [0x10, 0x45, 0x00],
-            [0x05, 0x4A, 0x00],
-            [0x00, 0x47, 0x2E],
-            [0x00, 0x41, 0x66],
-            [0x00, 0x00, 0x00],
-            [0x05, 0x05, 0x05],
-            [0x05, 0x05, 0x05],
-            [0xC7, 0xC7, 0xC7],
-            [0x00, 0x77, 0xFF],
-            [0x21, 0x55, 0xFF],
-            [0x82, 0x37, 0xFA],
-            [0xEB, 0x2F, 0xB5],
-            [0xFF, 0x29, 0x50],
-            [0xFF, 0x22, 0x00],
-            [0xD6, 0x32, 0x00],
-            [0xC4, 0x62, 0x00],
-            [0x35, 0x80, 0x00],
-            [0x05, 0x8F, 0x00],
-            [0x00, 0x8A, 0x55],
-            [0x00, 0x99, 0xCC],
-            [0x21, 0x21, 0x21],
-            [0x09, 0x09, 0x09],
-            [0x09, 0x09, 0x09],
-            [0xFF, 0xFF, 0xFF],
-            [0x0F, 0xD7, 0xFF],
-            [0x69, 0xA2, 0xFF],
-            [0xD4, 0x80, 0xFF],
-            [0xFF, 0x45, 0xF3],
-            [0xFF, 0x61, 0x8B],
-            [0xFF, 0x88, 0x33],
-            [0xFF, 0x9C, 0x12],
-            [0xFA, 0xBC, 0x20],
-            [0x9F, 0xE3, 0x0E],
-            [0x2B, 0xF0, 0x35],
-            [0x0C, 0xF0, 0xA4],
-            [0x05, 0xFB, 0xFF],
-            [0x5E, 0x5E, 0x5E],
-            [0x0D, 0x0D, 0x0D],
-            [0x0D, 0x0D, 0x0D],
-            [0xFF, 0xFF, 0xFF],
-            [0xA6, 0xFC, 0xFF],
-            [0xB3, 0xEC, 0xFF],
-            [0xDA, 0xAB, 0xEB],
-            [0xFF, 0xA8, 0xF9],
-            [0xFF, 0xAB, 0xB3],
-            [0xFF, 0xD2, 0xB0],
-            [0xFF, 0xEF, 0xA6],
-            [0xFF, 0xF7, 0x9C],
-            [0xD7, 0xE8, 0x95],
-            [0xA6, 0xED, 0xAF],
-            [0xA2, 0xF2, 0xDA],
-            [0x99, 0xFF, 0xFC],
-            [0xDD, 0xDD, 0xDD],
-            [0x11, 0x11, 0x11],
-            [0x11, 0x11, 0x11],
-        ];
+        let colors = Palette::default().colors;
 
         Self { buffer, colors }
     }
 
-    fn pick_offset(&self, x: u8, y: u8) -> usize {
-        let pitch = TILE_COUNTS_ON_WIDTH as usize * 8 * 3;
-        let offset = (y as usize) * pitch + x as usize * 3;
-        offset as usize
-    }
-
-    pub fn insert_color(&mut self, x: u8, y: u8, colors_idx: usize) {
-        let offset = self.pick_offset(x, y);
-        let color = self.colors[colors_idx];
-        for n in 0..3 as usize {
-            self.buffer[offset + n] = color[n];
-        }
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.colors = palette.colors;
     }
 
-    pub fn insert_colors(&mut self, color_info: FxHashMap<u8, usize>, y: u8) {
-        for (x, colors_idx) in color_info {
-            let offset = self.pick_offset(x, y);
-            let color = self.colors[colors_idx as usize];
-            for n in 0..3 as usize {
-                self.buffer[offset + n] = color[n];
-            }
+    pub fn convert(&mut self, frame_buffer: &FrameBuffer) {
+        for (rgb, idx) in self.buffer.chunks_mut(3).zip(frame_buffer.pixels.iter()) {
+            rgb.copy_from_slice(&self.colors[*idx as usize]);
         }
     }
 }
@@ -0,0 +1,269 @@
+use crate::emulator::configure::{PPU_DRAW_LINE_CYCLE, VISIBLE_LINES};
+use crate::ppu::event_log::{PpuEvent, PpuEventKind};
+use crate::ppu::oam::SpriteInfo;
+use crate::ppu::snapshot::PpuSnapshot;
+use std::fs::{create_dir_all, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+const SCROLL_RECT_COLOR: [u8; 3] = [0xFF, 0x00, 0xFF];
+const OAM_CELL_COLOR: [u8; 3] = [0x20, 0x20, 0x20];
+const VISIBLE_DOT_COLOR: [u8; 3] = [0x30, 0x30, 0x30];
+const BLANK_DOT_COLOR: [u8; 3] = [0x10, 0x10, 0x10];
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub buffer: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            buffer: vec![0; width * height * 3],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        let offset = (y * self.width + x) * 3;
+        self.buffer[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.set(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn blit(&mut self, x: usize, y: usize, image: &Image) {
+        for (dy, line) in image.buffer.chunks(image.width * 3).enumerate() {
+            let offset = ((y + dy) * self.width + x) * 3;
+            self.buffer[offset..offset + line.len()].copy_from_slice(line);
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&self.buffer)
+            .map_err(|e| e.to_string())
+    }
+}
+
+pub struct Viewer {
+    pub pattern_palette: u8,
+}
+
+impl Default for Viewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Viewer {
+    pub fn new() -> Self {
+        Self { pattern_palette: 0 }
+    }
+
+    pub fn select_next_pattern_palette(&mut self) {
+        self.pattern_palette = (self.pattern_palette + 1) % 8;
+    }
+
+    pub fn compose(&self, snapshot: &PpuSnapshot, colors: &[[u8; 3]; 512]) -> Image {
+        let mut image = Image::new(768 + PPU_DRAW_LINE_CYCLE as usize, 480);
+        image.blit(0, 0, &Self::nametables(snapshot, colors));
+        image.blit(512, 0, &self.pattern_tables(snapshot, colors));
+        image.blit(512, 128, &Self::palette_ram(snapshot, colors));
+        image.blit(512, 160, &Self::oam(snapshot, colors));
+        image.blit(768, 0, &Self::events(snapshot));
+        image
+    }
+
+    pub fn dump(
+        &self,
+        snapshot: &PpuSnapshot,
+        colors: &[[u8; 3]; 512],
+        dir: &Path,
+    ) -> Result<(), String> {
+        create_dir_all(dir).map_err(|e| e.to_string())?;
+        let images = [
+            ("nametables", Self::nametables(snapshot, colors)),
+            ("pattern_tables", self.pattern_tables(snapshot, colors)),
+            ("palette", Self::palette_ram(snapshot, colors)),
+            ("oam", Self::oam(snapshot, colors)),
+            ("events", Self::events(snapshot)),
+        ];
+        for (name, image) in images.iter() {
+            image.save_png(&dir.join(format!("{}_{}.png", snapshot.frame, name)))?;
+        }
+        Ok(())
+    }
+
+    fn tile_row(snapshot: &PpuSnapshot, addr: u16) -> [u8; 8] {
+        let pattern_tables = &snapshot.pattern_tables;
+        let (row, high) = (
+            pattern_tables[addr as usize],
+            pattern_tables[addr as usize + 8],
+        );
+        let mut pixels = [0; 8];
+        for (n, pixel) in pixels.iter_mut().enumerate() {
+            let shift = 7 - n;
+            *pixel = ((high >> shift) & 1) << 1 | ((row >> shift) & 1);
+        }
+        pixels
+    }
+
+    fn color(snapshot: &PpuSnapshot, colors: &[[u8; 3]; 512], palette: u8, pixel: u8) -> [u8; 3] {
+        let index = if pixel == 0 {
+            0
+        } else {
+            palette as usize * 4 + pixel as usize
+        };
+        colors[(snapshot.palette[index] & 0x3F) as usize]
+    }
+
+    pub fn nametables(snapshot: &PpuSnapshot, colors: &[[u8; 3]; 512]) -> Image {
+        let mut image = Image::new(512, 480);
+        let pattern_base = snapshot.ctrl.background_pattern_addr;
+        for nametable in 0..4 {
+            let base = nametable * 0x400;
+            let (origin_x, origin_y) = ((nametable & 1) * 256, (nametable >> 1) * 240);
+            for tile_y in 0..30 {
+                for tile_x in 0..32 {
+                    let tile = snapshot.nametables[base + tile_y * 32 + tile_x] as u16;
+                    let attr = snapshot.nametables[base + 0x3C0 + (tile_y / 4) * 8 + tile_x / 4];
+                    let shift = ((tile_y & 2) << 1) | (tile_x & 2);
+                    let palette = (attr >> shift) & 0b11;
+                    for row in 0..8 {
+                        let pixels = Self::tile_row(snapshot, pattern_base + tile * 0x10 + row);
+                        for (n, pixel) in pixels.iter().enumerate() {
+                            let color = Self::color(snapshot, colors, palette, *pixel);
+                            let x = origin_x + tile_x * 8 + n;
+                            let y = origin_y + tile_y * 8 + row as usize;
+                            image.set(x, y, color);
+                        }
+                    }
+                }
+            }
+        }
+        Self::draw_scroll_rect(snapshot, &mut image);
+        image
+    }
+
+    // The frame starts from t, so its scroll bits give the top left corner of the screen.
+    fn draw_scroll_rect(snapshot: &PpuSnapshot, image: &mut Image) {
+        let t = snapshot.t as usize;
+        let scroll_x = ((t >> 10) & 1) * 256 + ((t & 0x1F) << 3) + snapshot.x as usize;
+        let scroll_y = ((t >> 11) & 1) * 240 + (((t >> 5) & 0x1F) << 3) + ((t >> 12) & 0b111);
+        for n in 0..256 {
+            let x = (scroll_x + n) % 512;
+            image.set(x, scroll_y % 480, SCROLL_RECT_COLOR);
+            image.set(x, (scroll_y + 239) % 480, SCROLL_RECT_COLOR);
+        }
+        for n in 0..240 {
+            let y = (scroll_y + n) % 480;
+            image.set(scroll_x % 512, y, SCROLL_RECT_COLOR);
+            image.set((scroll_x + 255) % 512, y, SCROLL_RECT_COLOR);
+        }
+    }
+
+    pub fn pattern_tables(&self, snapshot: &PpuSnapshot, colors: &[[u8; 3]; 512]) -> Image {
+        let mut image = Image::new(256, 128);
+        for tile in 0..512 {
+            let (origin_x, origin_y) =
+                ((tile / 256) * 128 + (tile % 16) * 8, ((tile / 16) % 16) * 8);
+            for row in 0..8 {
+                let pixels = Self::tile_row(snapshot, tile as u16 * 0x10 + row as u16);
+                for (n, pixel) in pixels.iter().enumerate() {
+                    let color = Self::color(snapshot, colors, self.pattern_palette, *pixel);
+                    image.set(origin_x + n, origin_y + row, color);
+                }
+            }
+        }
+        image
+    }
+
+    pub fn palette_ram(snapshot: &PpuSnapshot, colors: &[[u8; 3]; 512]) -> Image {
+        let mut image = Image::new(256, 32);
+        for (n, index) in snapshot.palette.iter().enumerate() {
+            let color = colors[(index & 0x3F) as usize];
+            image.fill((n % 16) * 16, (n / 16) * 16, 16, 16, color);
+        }
+        image
+    }
+
+    pub fn oam(snapshot: &PpuSnapshot, colors: &[[u8; 3]; 512]) -> Image {
+        let mut image = Image::new(256, 256);
+        image.fill(0, 0, 256, 256, OAM_CELL_COLOR);
+        let height = snapshot.ctrl.sprite_height;
+        let sprite_ptn_table_addr = snapshot.ctrl.sprite_pattern_addr != 0;
+        for (n, bytes) in snapshot.oam.chunks(4).enumerate() {
+            let sprite_info = SpriteInfo::from_bytes(bytes);
+            let (origin_x, origin_y) = ((n % 8) * 32 + 8, (n / 8) * 32 + (16 - height as usize));
+            for row in 0..height as u16 {
+                let addr = sprite_info.pattern_addr(row, height, sprite_ptn_table_addr);
+                let mut pixels = Self::tile_row(snapshot, addr);
+                if sprite_info.attr.flip_sprite_horizontally {
+                    pixels.reverse();
+                }
+                for (x, pixel) in pixels.iter().enumerate() {
+                    if *pixel == 0 {
+                        continue;
+                    }
+                    let color = Self::color(snapshot, colors, sprite_info.attr.palette + 4, *pixel);
+                    let y = origin_y + row as usize * 2;
+                    image.fill(origin_x + x * 2, y, 2, 2, color);
+                }
+            }
+        }
+        image
+    }
+
+    fn event_color(event: &PpuEvent) -> [u8; 3] {
+        match (event.kind, event.addr) {
+            (PpuEventKind::Nmi, _) => [0xFF, 0xFF, 0xFF],
+            (PpuEventKind::Rti, _) => [0x80, 0x80, 0x80],
+            (PpuEventKind::Read, 0x2002) => [0xFF, 0xFF, 0x00],
+            (PpuEventKind::Read, _) => [0x00, 0x80, 0xFF],
+            (PpuEventKind::Write, 0x2000) => [0xFF, 0x40, 0x40],
+            (PpuEventKind::Write, 0x2001) => [0x40, 0xFF, 0x40],
+            (PpuEventKind::Write, 0x2005) => [0xFF, 0x80, 0x00],
+            (PpuEventKind::Write, 0x2006) => [0x00, 0xFF, 0xFF],
+            (PpuEventKind::Write, 0x4014) => [0xFF, 0x00, 0xFF],
+            (PpuEventKind::Write, 0x8000..=0xFFFF) => [0xA0, 0x60, 0xFF],
+            (PpuEventKind::Write, _) => [0x40, 0x80, 0x40],
+        }
+    }
+
+    pub fn events(snapshot: &PpuSnapshot) -> Image {
+        let total_lines = snapshot.total_lines as usize;
+        let mut image = Image::new(PPU_DRAW_LINE_CYCLE as usize, total_lines);
+        for y in 0..total_lines {
+            for x in 0..PPU_DRAW_LINE_CYCLE as usize {
+                let color = if y < VISIBLE_LINES as usize && (1..=256).contains(&x) {
+                    VISIBLE_DOT_COLOR
+                } else {
+                    BLANK_DOT_COLOR
+                };
+                image.set(x, y, color);
+            }
+        }
+        for event in snapshot.events.iter() {
+            image.set(
+                event.dot as usize,
+                event.scanline as usize,
+                Self::event_color(event),
+            );
+        }
+        image
+    }
+}
@@ -1,3 +1,6 @@
+pub mod region;
+
+use region::Region;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::fs::File;
@@ -7,6 +10,7 @@ use std::path::Path;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Nes {
     pub header: Header,
+    pub region: Region,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +21,7 @@ pub struct Header {
     flags8: Flags8,
     flags9: Flags9,
     flags10: Flags10,
+    flags12: Flags12,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,6 +76,7 @@ impl Header {
         let flags8 = Flags8::parse_buf(buffer[8]);
         let flags9 = Flags9::parse_buf(buffer[9]);
         let flags10 = Flags10::parse_buf(buffer[10]);
+        let flags12 = Flags12::parse_buf(buffer[12]);
 
         Self {
             info,
@@ -79,6 +85,17 @@ impl Header {
             flags8,
             flags9,
             flags10,
+            flags12,
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        if self.flags7.is_nes_20() {
+            self.flags12.region()
+        } else if self.flags9.tv_system || self.flags10.is_pal() {
+            Region::PAL
+        } else {
+            Region::NTSC
         }
     }
 }
@@ -149,6 +166,10 @@ impl Flags7 {
             mapper,
         }
     }
+
+    fn is_nes_20(&self) -> bool {
+        self.nes_20_format == 0b10
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -201,6 +222,32 @@ impl Flags10 {
             board_mode,
         }
     }
+
+    fn is_pal(&self) -> bool {
+        self.tv_system == 0b10
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Flags12 {
+    timing: u8,
+}
+
+impl Flags12 {
+    fn parse_buf(data: u8) -> Self {
+        let timing = data & 0b00000011;
+
+        Self { timing }
+    }
+
+    // Multi-region games run as NTSC.
+    fn region(&self) -> Region {
+        match self.timing {
+            1 => Region::PAL,
+            3 => Region::DENDY,
+            _ => Region::NTSC,
+        }
+    }
 }
 
 pub type Sprites = Vec<Vec<Vec<u32>>>;
@@ -213,12 +260,13 @@ impl Default for Nes {
 
 impl Nes {
     fn new() -> Self {
-        let file_path: Vec<String> = env::args().collect();
-        let file_path = Path::new(&file_path[file_path.len() - 1]);
+        let args: Vec<String> = env::args().collect();
+        let file_path = Path::new(&args[args.len() - 1]);
         let mut f = File::open(file_path).expect("File path need.");
         let mut buffer = Vec::new();
         f.read_to_end(&mut buffer).unwrap();
         let header = Header::new(&buffer);
-        Self { header }
+        let region = Region::from_args(&args).unwrap_or_else(|| header.region());
+        Self { header, region }
     }
 }
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Region {
+    NTSC,
+    PAL,
+    DENDY,
+}
+
+impl Region {
+    const NTSC_NOISE_PERIOD: [u16; 0x10] = [
+        4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+    ];
+    const PAL_NOISE_PERIOD: [u16; 0x10] = [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ];
+    // Quarter, half, quarter, half, then the cycle the sequence starts over on.
+    const NTSC_4STEP_CYCLES: [u32; 5] = [7457, 14913, 22371, 29829, 29830];
+    const NTSC_5STEP_CYCLES: [u32; 5] = [7457, 14913, 22371, 37281, 37282];
+    const PAL_4STEP_CYCLES: [u32; 5] = [8313, 16627, 24939, 33253, 33254];
+    const PAL_5STEP_CYCLES: [u32; 5] = [8313, 16627, 24939, 41565, 41566];
+    const NTSC_DMC_RATE: [u16; 0x10] = [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ];
+    const PAL_DMC_RATE: [u16; 0x10] = [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ];
+
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        args.iter()
+            .find_map(|arg| arg.strip_prefix("region="))
+            .map(|region| match region {
+                "ntsc" => Region::NTSC,
+                "pal" => Region::PAL,
+                "dendy" => Region::DENDY,
+                _ => panic!("Unknown region {}, use ntsc, pal or dendy.", region),
+            })
+    }
+
+    pub fn total_lines(&self) -> u16 {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL | Region::DENDY => 312,
+        }
+    }
+
+    pub fn vblank_line(&self) -> u16 {
+        match self {
+            Region::NTSC | Region::PAL => 241,
+            Region::DENDY => 291,
+        }
+    }
+
+    pub fn pre_render_line(&self) -> u16 {
+        self.total_lines() - 1
+    }
+
+    pub fn has_odd_frame_skip(&self) -> bool {
+        *self == Region::NTSC
+    }
+
+    pub fn cpu_clock_hz(&self) -> f32 {
+        match self {
+            Region::NTSC => 1789773.0,
+            Region::PAL => 1662607.0,
+            Region::DENDY => 1773448.0,
+        }
+    }
+
+    // 60.0988Hz on NTSC, where the skipped dot makes a frame 89341.5 dots on average.
+    pub fn frame_rate(&self) -> f32 {
+        let (numerator, denominator) = self.ppu_dots_per_cpu_cycle();
+        let dots_per_second = self.cpu_clock_hz() * numerator as f32 / denominator as f32;
+        let mut dots_per_frame = (341 * self.total_lines() as u32) as f32;
+        if self.has_odd_frame_skip() {
+            dots_per_frame -= 0.5;
+        }
+        dots_per_second / dots_per_frame
+    }
+
+    pub fn ppu_dots_per_cpu_cycle(&self) -> (u16, u16) {
+        match self {
+            Region::NTSC | Region::DENDY => (3, 1),
+            Region::PAL => (16, 5),
+        }
+    }
+
+    pub fn noise_periods(&self) -> &'static [u16; 0x10] {
+        match self {
+            Region::NTSC | Region::DENDY => &Self::NTSC_NOISE_PERIOD,
+            Region::PAL => &Self::PAL_NOISE_PERIOD,
+        }
+    }
+
+    pub fn dmc_rates(&self) -> &'static [u16; 0x10] {
+        match self {
+            Region::NTSC | Region::DENDY => &Self::NTSC_DMC_RATE,
+            Region::PAL => &Self::PAL_DMC_RATE,
+        }
+    }
+
+    pub fn frame_step_cycles(&self, is_5step: bool) -> &'static [u32; 5] {
+        match (self, is_5step) {
+            (Region::NTSC | Region::DENDY, false) => &Self::NTSC_4STEP_CYCLES,
+            (Region::NTSC | Region::DENDY, true) => &Self::NTSC_5STEP_CYCLES,
+            (Region::PAL, false) => &Self::PAL_4STEP_CYCLES,
+            (Region::PAL, true) => &Self::PAL_5STEP_CYCLES,
+        }
+    }
+}
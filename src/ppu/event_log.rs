@@ -0,0 +1,43 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PpuEventKind {
+    Read,
+    Write,
+    Nmi,
+    Rti,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PpuEvent {
+    pub kind: PpuEventKind,
+    pub addr: u16,
+    pub data: u8,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    current_frame: Vec<PpuEvent>,
+    last_frame: Vec<PpuEvent>,
+}
+
+impl EventLog {
+    pub fn push(&mut self, event: PpuEvent) {
+        self.current_frame.push(event);
+    }
+
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current_frame, &mut self.last_frame);
+        self.current_frame.clear();
+    }
+
+    pub fn events(&self) -> &[PpuEvent] {
+        &self.last_frame
+    }
+
+    pub fn current_events(&self) -> &[PpuEvent] {
+        &self.current_frame
+    }
+}
@@ -0,0 +1,49 @@
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+use serde_with::*;
+use std::hash::Hasher;
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+// Each pixel holds the palette index in bits 0-5 and the emphasis bits in 6-8.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameBuffer {
+    #[serde_as(as = "[_; 61440]")]
+    pub pixels: [u16; 61440],
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Self { pixels: [0; 61440] }
+    }
+
+    pub fn set(&mut self, x: u8, y: u8, idx: usize) {
+        self.pixels[y as usize * FRAME_WIDTH + x as usize] = idx as u16;
+    }
+
+    pub fn set_line(&mut self, y: u8, line: &[u16; FRAME_WIDTH]) {
+        let offset = y as usize * FRAME_WIDTH;
+        self.pixels[offset..offset + FRAME_WIDTH].copy_from_slice(line);
+    }
+
+    pub fn line(&self, y: u8) -> &[u16] {
+        let offset = y as usize * FRAME_WIDTH;
+        &self.pixels[offset..offset + FRAME_WIDTH]
+    }
+
+    pub fn hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        for pixel in self.pixels.iter() {
+            hasher.write_u16(*pixel);
+        }
+        hasher.finish()
+    }
+}
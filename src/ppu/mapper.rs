@@ -30,8 +30,6 @@ pub struct Map {
     name_and_attr_table_mirror: [u8; 0x0F00],
     background_table: [u8; 0x0010],
     pub sprite_pallet: [u8; 0x0010],
-    #[serde_as(as = "[_; 0x00E0]")]
-    background_and_sprite_pallet_mirror: [u8; 0x00E0],
     type_of_mirroring: TypeOfMirroring,
 }
 
@@ -52,14 +50,11 @@ impl Map {
             name_and_attr_table_mirror: [0; 0x0F00],
             background_table: [0; 0x0010],
             sprite_pallet: [0; 0x0010],
-            background_and_sprite_pallet_mirror: [0; 0x00E0],
             type_of_mirroring,
         }
     }
-}
 
-impl Mapper for Map {
-    fn addr(&mut self, addr: u16) -> u8 {
+    pub fn peek(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x0FFF => self.pattern_table_00[addr as usize],
             0x1000..=0x1FFF => self.pattern_table_01[(addr - 0x1000) as usize],
@@ -77,10 +72,16 @@ impl Mapper for Map {
                 self.background_table[(addr - 0x3F00) as usize]
             }
             0x3F10..=0x3F1F => self.sprite_pallet[(addr - 0x3F10) as usize],
-            0x3F20..=0x3FFF => self.background_and_sprite_pallet_mirror[(addr - 0x3F20) as usize],
+            0x3F20..=0x3FFF => self.peek(0x3F00 + (addr & 0x1F)),
             _ => unreachable!(),
         }
     }
+}
+
+impl Mapper for Map {
+    fn addr(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
 
     fn set(&mut self, addr: u16, data: u8) {
         match addr {
@@ -197,9 +198,7 @@ impl Mapper for Map {
             0x3F11..=0x3F13 | 0x3F15..=0x3F17 | 0x3F19..=0x3F1B | 0x03F1D..=0x03F1F => {
                 self.sprite_pallet[(addr - 0x3F10) as usize] = data
             }
-            0x3F20..=0x3FFF => {
-                self.background_and_sprite_pallet_mirror[(addr - 0x3F20) as usize] = data
-            }
+            0x3F20..=0x3FFF => self.set(0x3F00 + (addr & 0x1F), data),
             _ => (),
         };
     }
@@ -1,7 +1,14 @@
+pub mod event_log;
+pub mod frame_buffer;
 pub mod mapper;
 pub mod oam;
+pub mod snapshot;
 
+use crate::emulator::configure::{PPU_DRAW_LINE_CYCLE, VBLANK_START_CYCLE, VISIBLE_LINES};
+use crate::nes::region::Region;
 use crate::nes::*;
+use event_log::{EventLog, PpuEvent, PpuEventKind};
+use frame_buffer::FrameBuffer;
 use mapper::Map;
 
 use oam::*;
@@ -13,6 +20,16 @@ pub struct PPU {
     pub primary_oam: PrimaryOAM,
     pub secondary_oam: SecondaryOAM,
     pub oam_buf: Vec<u8>,
+    pub cycle: u16,
+    pub drawing_line: u16,
+    pub frame: u64,
+    pub suppress_vblank: bool,
+    pub suppress_nmi: bool,
+    pub nmi_pending: bool,
+    pub region: Region,
+    pub frame_buffer: FrameBuffer,
+    #[serde(skip)]
+    pub event_log: EventLog,
 }
 
 impl PPU {
@@ -22,25 +39,99 @@ impl PPU {
             primary_oam: PrimaryOAM::default(),
             secondary_oam: SecondaryOAM::default(),
             oam_buf: vec![],
+            cycle: 0,
+            drawing_line: 0,
+            frame: 0,
+            suppress_vblank: false,
+            suppress_nmi: false,
+            nmi_pending: false,
+            region: nes.region,
+            frame_buffer: FrameBuffer::default(),
+            event_log: EventLog::default(),
         }
     }
 
-    pub fn set_secondary_oam(&mut self, y: u8, behind_background: bool) {
-        let mut sprite_infos: SpriteInfos = vec![];
-        for sprite_info in &self.primary_oam.sprite_infos {
-            let match_condition = if behind_background {
-                sprite_info.in_drawing_range(y) && sprite_info.behind_of_background()
-            } else {
-                sprite_info.in_drawing_range(y) && sprite_info.front_of_background()
-            };
-
-            if match_condition {
-                sprite_infos.push(sprite_info.to_owned());
-                if sprite_infos.len() >= 8 {
-                    break;
-                }
+    fn frame_dot(&self) -> u32 {
+        self.drawing_line as u32 * PPU_DRAW_LINE_CYCLE as u32 + self.cycle as u32
+    }
+
+    fn vblank_dot(&self) -> u32 {
+        self.region.vblank_line() as u32 * PPU_DRAW_LINE_CYCLE as u32 + VBLANK_START_CYCLE as u32
+    }
+
+    // The PPU catches up after each instruction, so CPU accesses land ahead of its dot.
+    fn access_dot(&self, cpu_cycles: u16) -> u32 {
+        let (numerator, denominator) = self.region.ppu_dots_per_cpu_cycle();
+        self.frame_dot() + (cpu_cycles * numerator / denominator) as u32
+    }
+
+    pub fn is_just_before_vblank(&self, cpu_cycles: u16) -> bool {
+        self.access_dot(cpu_cycles) + 1 == self.vblank_dot()
+    }
+
+    pub fn is_just_after_vblank(&self, cpu_cycles: u16) -> bool {
+        (self.vblank_dot()..=self.vblank_dot() + 1).contains(&self.access_dot(cpu_cycles))
+    }
+
+    pub fn is_vblank_ahead(&self, cpu_cycles: u16) -> bool {
+        self.frame_dot() < self.vblank_dot() && self.access_dot(cpu_cycles) >= self.vblank_dot()
+    }
+
+    pub fn log_event(&mut self, kind: PpuEventKind, addr: u16, data: u8, cpu_cycles: u16) {
+        let access_dot = self.access_dot(cpu_cycles);
+        let line_dots = PPU_DRAW_LINE_CYCLE as u32;
+        self.event_log.push(PpuEvent {
+            kind,
+            addr,
+            data,
+            scanline: (access_dot / line_dots % self.region.total_lines() as u32) as u16,
+            dot: (access_dot % line_dots) as u16,
+        });
+    }
+
+    pub fn is_rendering_line(&self) -> bool {
+        self.drawing_line < VISIBLE_LINES || self.drawing_line == self.region.pre_render_line()
+    }
+
+    pub fn set_secondary_oam(
+        &mut self,
+        y: u8,
+        behind_background: bool,
+        height: u8,
+        sprite_limit: Option<usize>,
+    ) {
+        let sprite_infos: SpriteInfos = self
+            .primary_oam
+            .sprite_infos
+            .iter()
+            .filter(|sprite_info| sprite_info.in_drawing_range(y, height))
+            .take(sprite_limit.unwrap_or(usize::MAX))
+            .filter(|sprite_info| sprite_info.behind_of_background() == behind_background)
+            .cloned()
+            .collect();
+        self.secondary_oam.sprite_infos = sprite_infos;
+    }
+
+    // Hardware evaluation on a visible line always stops at 8 sprites. After that it
+    // compares bytes diagonally through OAM, so the flag misses or fakes overflows.
+    pub fn is_sprite_overflow(&self, line: u16, height: u8) -> bool {
+        let sprite_infos = &self.primary_oam.sprite_infos;
+        let in_range = |y: u8| line.wrapping_sub(y as u16) < height as u16;
+        let (mut n, mut count) = (0, 0);
+        while n < sprite_infos.len() && count < 8 {
+            if in_range(sprite_infos[n].pos_y) {
+                count += 1;
             }
+            n += 1;
         }
-        self.secondary_oam.sprite_infos = sprite_infos;
+        let mut m = 0;
+        while n < sprite_infos.len() {
+            if in_range(sprite_infos[n].to_bytes()[m]) {
+                return true;
+            }
+            n += 1;
+            m = (m + 1) & 0b11;
+        }
+        false
     }
 }
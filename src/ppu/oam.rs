@@ -24,8 +24,62 @@ impl SpriteInfo {
         }
     }
 
-    pub fn in_drawing_range(&self, y: u8) -> bool {
-        (self.pos_y <= y) && (self.pos_y + 7 >= y)
+    // Sprites are evaluated one line ahead, so OAM Y is the line above the top row.
+    pub fn in_drawing_range(&self, y: u8, height: u8) -> bool {
+        let (y, pos_y) = (y as u16, self.pos_y as u16 + 1);
+        (pos_y <= y) && (pos_y + height as u16 > y)
+    }
+
+    pub fn relative_hight(&self, y: u16) -> u16 {
+        y - self.pos_y as u16 - 1
+    }
+
+    pub fn pattern_addr(
+        &self,
+        relative_hight: u16,
+        height: u8,
+        sprite_ptn_table_addr: bool,
+    ) -> u16 {
+        let row = if self.attr.flip_sprite_vertically {
+            height as u16 - 1 - relative_hight
+        } else {
+            relative_hight
+        };
+        match height {
+            // 8x16 sprites pick the bank from bit 0 of the tile index and place the
+            // bottom half in the next tile, so vertical flip swaps the two tiles.
+            16 => {
+                let tile_number = (self.tile_index.tile_number & 0xFE) as u16 + row / 8;
+                self.tile_index.bank_of_tile as u16 * 0x1000 + tile_number * 0x10 + row % 8
+            }
+            _ => {
+                sprite_ptn_table_addr as u16 * 0x1000
+                    + self.tile_index.tile_number as u16 * 0x10
+                    + row
+            }
+        }
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut tile_index = TileIndex::default();
+        tile_index.set(data[1]);
+        let mut attr = Attr::default();
+        attr.set(data[2]);
+        Self {
+            pos_y: data[0],
+            tile_index,
+            attr,
+            pos_x: data[3],
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        [
+            self.pos_y,
+            self.tile_index.tile_number,
+            self.attr.to_n(),
+            self.pos_x,
+        ]
     }
 
     pub fn behind_of_background(&self) -> bool {
@@ -58,24 +112,12 @@ impl PrimaryOAM {
     }
 
     pub fn put_sprite_info(&mut self, data: &Vec<u8>, target: u8) {
-        let mut tile_index = TileIndex::default();
-        tile_index.set(data[1]);
-        let mut attr = Attr::default();
-        attr.set(data[2]);
-
-        let sprite_info = SpriteInfo {
-            pos_y: data[0],
-            tile_index,
-            attr,
-            pos_x: data[3],
-        };
-
-        self.sprite_infos[target as usize] = sprite_info;
+        self.sprite_infos[target as usize] = SpriteInfo::from_bytes(data);
     }
 
     pub fn set_sprite_infos(&mut self, v: Vec<u8>) {
         let mut sprite_infos: SpriteInfos = vec![];
-        for i in 0..63 {
+        for i in 0..64 {
             let sprite_idx = i * 4;
             let mut tile_index = TileIndex::default();
             tile_index.set(v[(sprite_idx + 1) as usize]);
@@ -116,6 +158,7 @@ impl TileIndex {
 
     fn set(&mut self, data: u8) {
         self.tile_number = data;
+        self.bank_of_tile = (data & 0b00000001) != 0;
     }
 }
 
@@ -152,6 +195,14 @@ impl Attr {
         self.unimplemented = data & 0b00011100;
         self.palette = data & 0b00000011;
     }
+
+    fn to_n(&self) -> u8 {
+        (self.flip_sprite_vertically as u8) << 7
+            | (self.flip_sprite_horizontally as u8) << 6
+            | (self.priority as u8) << 5
+            | self.unimplemented
+            | self.palette
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -176,12 +227,4 @@ impl SecondaryOAM {
         let sprite_infos = vec![];
         self.sprite_infos = sprite_infos;
     }
-
-    pub fn pick_sprite_info_with_x(&self, x: u8) -> Option<&SpriteInfo> {
-        let sprite_info = self
-            .sprite_infos
-            .iter()
-            .find(|sprite_info| (sprite_info.pos_x == x));
-        return sprite_info;
-    }
 }
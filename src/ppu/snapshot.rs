@@ -0,0 +1,126 @@
+use crate::bus::cpu_map::{PpuCtrl, PpuMask, PpuRegister, PpuStatus};
+use crate::ppu::event_log::PpuEvent;
+use crate::ppu::PPU;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PpuCtrlSnapshot {
+    pub nmi_enabled: bool,
+    pub ppu_selector: bool,
+    pub sprite_height: u8,
+    pub background_pattern_addr: u16,
+    pub sprite_pattern_addr: u16,
+    pub vram_increment: u16,
+    pub base_nametable_addr: u16,
+}
+
+impl PpuCtrlSnapshot {
+    fn new(ppu_ctrl: &PpuCtrl) -> Self {
+        Self {
+            nmi_enabled: ppu_ctrl.gen_nmi,
+            ppu_selector: ppu_ctrl.is_ppu_selector(),
+            sprite_height: ppu_ctrl.sprite_height(),
+            background_pattern_addr: ppu_ctrl.is_deep_bk_index() as u16 * 0x1000,
+            sprite_pattern_addr: ppu_ctrl.sprite_ptn_table_addr as u16 * 0x1000,
+            vram_increment: ppu_ctrl.increment_vram_num(),
+            base_nametable_addr: 0x2000 + ppu_ctrl.base_name_table_addr as u16 * 0x400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PpuMaskSnapshot {
+    pub gray_scale: bool,
+    pub show_background_in_leftmost: bool,
+    pub show_sprites_in_leftmost: bool,
+    pub show_background: bool,
+    pub show_sprites: bool,
+    pub emphasis: u8,
+}
+
+impl PpuMaskSnapshot {
+    fn new(ppu_mask: &PpuMask) -> Self {
+        Self {
+            gray_scale: ppu_mask.is_gray_scale(),
+            show_background_in_leftmost: ppu_mask.is_show_background_in_leftmost(),
+            show_sprites_in_leftmost: ppu_mask.is_show_sprites_in_leftmost(),
+            show_background: ppu_mask.is_show_background(),
+            show_sprites: ppu_mask.is_show_sprites(),
+            emphasis: ppu_mask.emphasis() as u8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PpuStatusSnapshot {
+    pub in_vblank: bool,
+    pub sprite_zero_hit: bool,
+    pub sprite_overflow: bool,
+}
+
+impl PpuStatusSnapshot {
+    fn new(ppu_status: &PpuStatus) -> Self {
+        Self {
+            in_vblank: ppu_status.in_vlank,
+            sprite_zero_hit: ppu_status.is_occured_sprite_zero_hit(),
+            sprite_overflow: ppu_status.is_sprite_overflow(),
+        }
+    }
+}
+
+// Nametables are the $2000-$2FFF view after mirroring, events those of the last frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PpuSnapshot {
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    pub total_lines: u16,
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
+    pub ctrl: PpuCtrlSnapshot,
+    pub mask: PpuMaskSnapshot,
+    pub status: PpuStatusSnapshot,
+    pub oam_addr: u8,
+    pub pattern_tables: Vec<u8>,
+    pub nametables: Vec<u8>,
+    pub palette: Vec<u8>,
+    pub oam: Vec<u8>,
+    pub events: Vec<PpuEvent>,
+}
+
+impl PpuSnapshot {
+    pub fn new(ppu: &PPU, ppu_register: &PpuRegister) -> Self {
+        let internal_registers = &ppu_register.internal_registers;
+        let peek_range = |start: u16, len: u16| -> Vec<u8> {
+            (start..start + len)
+                .map(|addr| ppu.map.peek(addr))
+                .collect()
+        };
+        Self {
+            scanline: ppu.drawing_line,
+            dot: ppu.cycle,
+            frame: ppu.frame,
+            total_lines: ppu.region.total_lines(),
+            v: internal_registers.current_vram,
+            t: internal_registers.temporary_vram,
+            x: internal_registers.x_scroll,
+            w: internal_registers.is_latched(),
+            ctrl: PpuCtrlSnapshot::new(&ppu_register.ppu_ctrl),
+            mask: PpuMaskSnapshot::new(&ppu_register.ppu_mask),
+            status: PpuStatusSnapshot::new(&ppu_register.ppu_status),
+            oam_addr: ppu_register.oam_addr(),
+            pattern_tables: peek_range(0x0000, 0x2000),
+            nametables: peek_range(0x2000, 0x1000),
+            palette: peek_range(0x3F00, 0x20),
+            oam: ppu
+                .primary_oam
+                .sprite_infos
+                .iter()
+                .flat_map(|sprite_info| sprite_info.to_bytes())
+                .collect(),
+            events: ppu.event_log.events().to_vec(),
+        }
+    }
+}